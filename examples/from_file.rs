@@ -1,9 +1,5 @@
 use grep_cli::stdout;
-use ripline::{
-    line_buffer::{LineBufferBuilder, LineBufferReader},
-    lines::LineIter,
-    LineTerminator,
-};
+use ripline::line_reader::LineReader;
 use std::{env, error::Error, fs::File, io::Write, path::PathBuf};
 use termcolor::ColorChoice;
 
@@ -12,18 +8,11 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let mut out = stdout(ColorChoice::Never);
 
-    let reader = File::open(&path)?;
-    let terminator = LineTerminator::byte(b'\n');
-    let mut line_buffer = LineBufferBuilder::new().build();
-    let mut lb_reader = LineBufferReader::new(reader, &mut line_buffer);
-
-    while lb_reader.fill()? {
-        let lines = LineIter::new(terminator.as_byte(), lb_reader.buffer());
-        for line in lines {
-            out.write_all(line)?;
-        }
-        lb_reader.consume_all();
-    }
+    let mut reader = LineReader::new(File::open(&path)?);
+    reader.for_each_line(|line| {
+        out.write_all(line)?;
+        Ok(true)
+    })?;
 
     Ok(())
 }
@@ -1,6 +1,7 @@
 use bstr::{io::BufReadExt, ByteSlice};
 use memmap2::Mmap;
 use ripline::{
+    count,
     line_buffer::{LineBufferBuilder, LineBufferReader},
     lines::LineIter,
     LineTerminator,
@@ -13,9 +14,6 @@ use std::time::{Duration, Instant};
 
 extern crate linereader;
 use linereader::LineReader;
-extern crate memchr;
-
-use memchr::Memchr;
 
 const BUFFER_SIZE: usize = 1024 * 64;
 
@@ -26,6 +24,8 @@ struct Report {
 
 impl Report {
     fn new(filename: &str) -> Self {
+        // Count with a plain byte loop, so that the reference doesn't depend
+        // on any of the code being benchmarked.
         let mut infile = File::open(filename).expect("open");
 
         let mut lines = 0_u64;
@@ -36,7 +36,7 @@ impl Report {
                 break;
             }
             bytes += r as u64;
-            lines += Memchr::new(b'\n', &buf[..r]).count() as u64;
+            lines += buf[..r].iter().filter(|&&b| b == b'\n').count() as u64;
         }
 
         println!("File: {}, bytes: {}, lines: {}", filename, bytes, lines);
@@ -141,7 +141,7 @@ fn try_ripline(report: &Report, filename: &str) {
     let mut bytes = 0_u64;
 
     while line_buffer_reader.fill().unwrap() {
-        let iter = LineIter::new(terminator.as_byte(), line_buffer_reader.buffer());
+        let iter = LineIter::new(terminator, line_buffer_reader.buffer());
         for line in iter {
             bytes += line.len() as u64;
             lines += 1;
@@ -168,7 +168,7 @@ fn try_ripline_memmap(report: &Report, filename: &str) {
     let mut lines = 0_u64;
     let mut bytes_read = 0_u64;
 
-    let iter = LineIter::new(terminator.as_byte(), bytes);
+    let iter = LineIter::new(terminator, bytes);
     for line in iter {
         bytes_read += line.len() as u64;
         lines += 1;
@@ -182,6 +182,18 @@ fn try_ripline_memmap(report: &Report, filename: &str) {
     );
 }
 
+fn try_ripline_count(report: &Report, filename: &str) {
+    let start = Instant::now();
+    let count = count::count_path(filename).expect("count");
+
+    report.report(
+        "ripline_count()",
+        Some(count.bytes()),
+        Some(count.lines()),
+        start.elapsed(),
+    );
+}
+
 fn try_read_until(report: &Report, filename: &str) {
     let infile = File::open(filename).expect("open");
     let mut infile = BufReader::with_capacity(BUFFER_SIZE, infile);
@@ -256,6 +268,7 @@ fn main() {
         try_linereader(&report, &file);
         try_ripline(&report, &file);
         try_ripline_memmap(&report, &file);
+        try_ripline_count(&report, &file);
         try_bstr_for_line(&report, &file);
         try_read_until(&report, &file);
         try_read_line(&report, &file);
@@ -0,0 +1,264 @@
+/*!
+An asynchronous counterpart to `LineBufferReader` for `tokio::io::AsyncRead`.
+
+```no_run
+use ripline::{async_reader::AsyncLineBufferReader, line_buffer::LineBufferBuilder};
+
+# async fn example(socket: tokio::io::DuplexStream) -> std::io::Result<()> {
+let mut line_buffer = LineBufferBuilder::new().build();
+let mut lb_reader = AsyncLineBufferReader::new(socket, &mut line_buffer);
+while lb_reader.fill().await? {
+    lb_reader.consume_all();
+}
+# Ok(())
+# }
+```
+*/
+
+use std::io;
+
+use bstr::ByteSlice;
+use futures_util::stream::{self, Stream};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::line_buffer::{Fragment, LineBuffer, NumberedLines};
+use crate::Error;
+
+/// An asynchronous line buffer reader efficiently reads a line oriented
+/// buffer from an arbitrary asynchronous reader.
+///
+/// This behaves exactly like `LineBufferReader`, except that `fill` is an
+/// `async fn`.
+#[derive(Debug)]
+pub struct AsyncLineBufferReader<'b, R> {
+    rdr: R,
+    line_buffer: &'b mut LineBuffer,
+}
+
+impl<'b, R: AsyncRead + Unpin> AsyncLineBufferReader<'b, R> {
+    /// Create a new buffered reader that reads from `rdr` and uses the given
+    /// `line_buffer` as an intermediate buffer.
+    pub fn new(rdr: R, line_buffer: &'b mut LineBuffer) -> AsyncLineBufferReader<'b, R> {
+        line_buffer.clear();
+        AsyncLineBufferReader { rdr, line_buffer }
+    }
+
+    /// Return a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.rdr
+    }
+
+    /// Return a mutable reference to the underlying reader.
+    ///
+    /// Reading from the underlying reader directly will cause the contents of
+    /// this buffer to skip the data that was read.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.rdr
+    }
+
+    /// The absolute byte offset which corresponds to the starting offsets
+    /// of the data returned by `buffer` relative to the beginning of the
+    /// underlying reader's contents.
+    pub fn absolute_byte_offset(&self) -> u64 {
+        self.line_buffer.absolute_byte_offset()
+    }
+
+    /// If binary data was detected, then this returns the absolute byte offset
+    /// at which binary data was initially found.
+    pub fn binary_byte_offset(&self) -> Option<u64> {
+        self.line_buffer.binary_byte_offset()
+    }
+
+    /// The line number, starting at `1`, of the first line returned by
+    /// `buffer`, if line counting was enabled with
+    /// `LineBufferBuilder::line_number`.
+    pub fn line_number(&self) -> Option<u64> {
+        self.line_buffer.line_number()
+    }
+
+    /// Return an iterator over the lines in this buffer, along with their line
+    /// numbers and absolute byte offsets.
+    ///
+    /// This has the same semantics as `LineBufferReader::numbered_lines`.
+    pub fn numbered_lines(&self) -> Option<NumberedLines<'_>> {
+        self.line_buffer.numbered_lines()
+    }
+
+    /// Return up to the last `n` lines that were consumed from this buffer,
+    /// right before the contents of `buffer`.
+    ///
+    /// See `LineBufferReader::before_context` for details.
+    pub fn before_context(&self, n: usize) -> &[u8] {
+        self.line_buffer.before_context(n)
+    }
+
+    /// Return the largest size, in bytes, that the underlying line buffer has
+    /// ever grown to.
+    pub fn high_water_mark(&self) -> usize {
+        self.line_buffer.high_water_mark()
+    }
+
+    /// Return the absolute byte offsets of the lines that were cut or dropped
+    /// so far, in input order.
+    ///
+    /// This has the same semantics as `LineBufferReader::overlong_lines`.
+    pub fn overlong_lines(&self) -> &[u64] {
+        self.line_buffer.overlong_lines()
+    }
+
+    /// If the contents of this buffer are a piece of a line that is too long
+    /// to buffer in full, then return which piece it is.
+    ///
+    /// This has the same semantics as `LineBufferReader::fragment`.
+    pub fn fragment(&self) -> Option<Fragment> {
+        self.line_buffer.fragment()
+    }
+
+    /// Fill the contents of this buffer by discarding the part of the buffer
+    /// that has been consumed. The free space created by discarding the
+    /// consumed part of the buffer is then filled with new data from the
+    /// reader.
+    ///
+    /// If EOF is reached, then `false` is returned. Otherwise, `true` is
+    /// returned.
+    ///
+    /// This has the same semantics as `LineBufferReader::fill`.
+    pub async fn fill(&mut self) -> Result<bool, Error> {
+        let mut more = self.fill_once().await?;
+        while more && !self.line_buffer.fill_done() {
+            more = self.fill_once().await?;
+        }
+        Ok(more)
+    }
+
+    /// Fill the buffer until it holds at least one more line, regardless of
+    /// the fill policy.
+    async fn fill_once(&mut self) -> Result<bool, Error> {
+        if let Some(more) = self.line_buffer.fill_start() {
+            return Ok(more);
+        }
+        loop {
+            self.line_buffer.ensure_capacity()?;
+            let readlen = match self
+                .rdr
+                .read(self.line_buffer.free_buffer().as_bytes_mut())
+                .await
+            {
+                Ok(readlen) => readlen,
+                Err(err) => return Err(self.line_buffer.io_error(err)),
+            };
+            if let Some(more) = self.line_buffer.fill_advance(readlen) {
+                return Ok(more);
+            }
+        }
+    }
+
+    /// Return true if the last fill reached EOF, or binary data under
+    /// `BinaryDetection::Quit`.
+    ///
+    /// This has the same semantics as `LineBufferReader::at_eof`.
+    pub fn at_eof(&self) -> bool {
+        self.line_buffer.at_eof()
+    }
+
+    /// Return the contents of this buffer.
+    pub fn buffer(&self) -> &[u8] {
+        self.line_buffer.buffer()
+    }
+
+    /// Return the buffer as a BStr, used for convenient equality checking
+    pub fn bstr(&self) -> &::bstr::BStr {
+        self.buffer().as_bstr()
+    }
+
+    /// Consume the number of bytes provided. This must be less than or equal
+    /// to the number of bytes returned by `buffer`.
+    pub fn consume(&mut self, amt: usize) {
+        self.line_buffer.consume(amt);
+    }
+
+    /// Consumes the remainder of the buffer. Subsequent calls to `buffer` are
+    /// guaranteed to return an empty slice until the buffer is refilled.
+    ///
+    /// This is a convenience function for `consume(buffer.len())`.
+    pub fn consume_all(&mut self) {
+        self.line_buffer.consume_all();
+    }
+
+    /// Convert this reader into a stream of chunks of complete lines.
+    ///
+    /// Each item is a copy of the contents of the buffer after a fill, so
+    /// every chunk ends at a line terminator, except possibly the last one.
+    pub fn into_stream(self) -> impl Stream<Item = Result<Vec<u8>, io::Error>> + 'b
+    where
+        R: 'b,
+    {
+        stream::try_unfold(self, |mut rdr| async move {
+            if !rdr.fill().await? {
+                return Ok(None);
+            }
+            let chunk = rdr.buffer().to_vec();
+            rdr.consume_all();
+            Ok(Some((chunk, rdr)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_buffer::{BinaryDetection, LineBufferBuilder};
+    use futures_util::StreamExt;
+
+    #[tokio::test]
+    async fn buffer_basics() {
+        let bytes = "homer\nlisa\nmaggie";
+        let mut linebuf = LineBufferBuilder::new().build();
+        let mut rdr = AsyncLineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+
+        assert!(rdr.fill().await.unwrap());
+        assert_eq!(rdr.bstr(), "homer\nlisa\n");
+        rdr.consume(5);
+        assert_eq!(rdr.absolute_byte_offset(), 5);
+        rdr.consume_all();
+
+        assert!(rdr.fill().await.unwrap());
+        assert_eq!(rdr.bstr(), "maggie");
+        rdr.consume_all();
+
+        assert!(!rdr.fill().await.unwrap());
+        assert_eq!(rdr.absolute_byte_offset(), bytes.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn buffer_binary_quit() {
+        let bytes = "homer\nli\x00sa\nmaggie\n";
+        let mut linebuf = LineBufferBuilder::new()
+            .binary_detection(BinaryDetection::Quit(b'\x00'))
+            .build();
+        let mut rdr = AsyncLineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+
+        assert!(rdr.fill().await.unwrap());
+        assert_eq!(rdr.bstr(), "homer\nli");
+        rdr.consume_all();
+        assert!(!rdr.fill().await.unwrap());
+        assert_eq!(rdr.binary_byte_offset(), Some(8));
+    }
+
+    #[tokio::test]
+    async fn stream_chunks() {
+        let bytes = "homer\nlisa\nmaggie\nbart";
+        let mut linebuf = LineBufferBuilder::new().capacity(4).build();
+        let rdr = AsyncLineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+
+        let chunks: Vec<Vec<u8>> = rdr
+            .into_stream()
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+        assert_eq!(chunks.concat(), bytes.as_bytes());
+        for chunk in &chunks[..chunks.len() - 1] {
+            assert!(chunk.ends_with(b"\n"));
+        }
+    }
+}
@@ -0,0 +1,556 @@
+/*!
+A parallel reader for BGZF (blocked gzip) inputs.
+
+BGZF files are a series of independently compressed gzip members of at most
+64 KB of uncompressed data each. A `BgzfReader` reads raw blocks on the calling
+thread, inflates them on a pool of worker threads, and hands out the
+decompressed bytes in order. It is meant to be used as the reader of a
+`LineBufferReader`:
+
+```no_run
+use ripline::{
+    bgzf::BgzfReaderBuilder,
+    line_buffer::{LineBufferBuilder, LineBufferReader},
+};
+use std::fs::File;
+
+# fn example() -> Result<(), Box<dyn std::error::Error>> {
+let reader = BgzfReaderBuilder::new().threads(4).build(File::open("reads.tsv.gz")?);
+let mut line_buffer = LineBufferBuilder::new().build();
+let mut lb_reader = LineBufferReader::new(reader, &mut line_buffer);
+while lb_reader.fill()? {
+    // Remember where this buffer started so we can come back to it later.
+    let _start = lb_reader.virtual_offset();
+    lb_reader.consume_all();
+}
+# Ok(())
+# }
+```
+*/
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use crate::line_buffer::LineBufferReader;
+
+/// The maximum number of blocks whose offsets are remembered for computing
+/// virtual offsets. This covers at least 64 MB of uncompressed data behind the
+/// most recently decompressed block.
+const MAX_INDEXED_BLOCKS: usize = 1024;
+
+/// The number of blocks to keep in flight per worker thread.
+const BLOCKS_PER_THREAD: usize = 4;
+
+/// A BGZF virtual offset.
+///
+/// The upper 48 bits are the offset of a block in the compressed file, and the
+/// lower 16 bits are an offset into the uncompressed contents of that block.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct VirtualOffset(u64);
+
+impl VirtualOffset {
+    /// Create a new virtual offset from a compressed block offset and an
+    /// offset into the uncompressed contents of that block.
+    ///
+    /// # Panics
+    ///
+    /// This panics if `compressed` doesn't fit in 48 bits.
+    #[inline]
+    pub fn new(compressed: u64, uncompressed: u16) -> VirtualOffset {
+        assert!(compressed < (1 << 48));
+        VirtualOffset((compressed << 16) | u64::from(uncompressed))
+    }
+
+    /// Return the offset of the block in the compressed file.
+    #[inline]
+    pub fn compressed(&self) -> u64 {
+        self.0 >> 16
+    }
+
+    /// Return the offset into the uncompressed contents of the block.
+    #[inline]
+    pub fn uncompressed(&self) -> u16 {
+        self.0 as u16
+    }
+}
+
+impl From<u64> for VirtualOffset {
+    #[inline]
+    fn from(offset: u64) -> VirtualOffset {
+        VirtualOffset(offset)
+    }
+}
+
+impl From<VirtualOffset> for u64 {
+    #[inline]
+    fn from(offset: VirtualOffset) -> u64 {
+        offset.0
+    }
+}
+
+/// A builder for constructing BGZF readers.
+#[derive(Clone, Debug)]
+pub struct BgzfReaderBuilder {
+    threads: usize,
+}
+
+impl Default for BgzfReaderBuilder {
+    fn default() -> BgzfReaderBuilder {
+        BgzfReaderBuilder::new()
+    }
+}
+
+impl BgzfReaderBuilder {
+    /// Create a new builder for a BGZF reader.
+    pub fn new() -> BgzfReaderBuilder {
+        BgzfReaderBuilder {
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+
+    /// Set the number of threads used to decompress blocks.
+    ///
+    /// By default, this is set to the available parallelism of the machine. A
+    /// value of `0` is treated as `1`.
+    pub fn threads(&mut self, threads: usize) -> &mut BgzfReaderBuilder {
+        self.threads = threads;
+        self
+    }
+
+    /// Create a new BGZF reader that reads blocks from `rdr`.
+    ///
+    /// The position of `rdr` is taken to be offset `0` of the compressed file.
+    pub fn build<R: io::Read>(&self, rdr: R) -> BgzfReader<R> {
+        let threads = self.threads.max(1);
+        let (jobs, rx) = mpsc::channel::<Job>();
+        let rx = Arc::new(Mutex::new(rx));
+        let workers = (0..threads)
+            .map(|_| {
+                let rx = Arc::clone(&rx);
+                thread::spawn(move || worker(rx))
+            })
+            .collect();
+        BgzfReader {
+            rdr,
+            jobs: Some(jobs),
+            workers,
+            max_in_flight: threads * BLOCKS_PER_THREAD,
+            pending: VecDeque::new(),
+            coffset: 0,
+            eof: false,
+            block: vec![],
+            block_pos: 0,
+            skip: 0,
+            delivered: 0,
+            index: VecDeque::new(),
+        }
+    }
+}
+
+/// A raw block to inflate, along with where to send the result.
+type Job = (Vec<u8>, mpsc::SyncSender<io::Result<Vec<u8>>>);
+
+/// A block that has been handed to a worker.
+#[derive(Debug)]
+struct Pending {
+    coffset: u64,
+    clen: u64,
+    result: mpsc::Receiver<io::Result<Vec<u8>>>,
+}
+
+/// The location of a block whose contents have been handed out.
+#[derive(Clone, Copy, Debug)]
+struct BlockInfo {
+    /// The offset of the block in the compressed file.
+    coffset: u64,
+    /// The length of the block in the compressed file.
+    clen: u64,
+    /// The absolute offset of the first byte handed out from this block.
+    ustart: u64,
+    /// The offset of the first byte handed out from this block, relative to
+    /// the start of the block's uncompressed contents.
+    first: u16,
+    /// The number of bytes handed out from this block.
+    ulen: u64,
+}
+
+/// A reader that decompresses BGZF blocks on a pool of threads.
+///
+/// Decompressed data is always handed out in input order.
+#[derive(Debug)]
+pub struct BgzfReader<R> {
+    rdr: R,
+    jobs: Option<mpsc::Sender<Job>>,
+    workers: Vec<thread::JoinHandle<()>>,
+    max_in_flight: usize,
+    /// Blocks that are being decompressed, in input order.
+    pending: VecDeque<Pending>,
+    /// The compressed offset of the next block to read from `rdr`.
+    coffset: u64,
+    /// Whether `rdr` has been exhausted.
+    eof: bool,
+    /// The uncompressed contents of the current block.
+    block: Vec<u8>,
+    /// The position of the next byte to hand out from `block`.
+    block_pos: usize,
+    /// The number of bytes to skip at the start of the next block, as set by
+    /// a seek.
+    skip: u16,
+    /// The number of uncompressed bytes handed out so far.
+    delivered: u64,
+    /// The most recently handed out blocks.
+    index: VecDeque<BlockInfo>,
+}
+
+impl<R: io::Read> BgzfReader<R> {
+    /// Create a new BGZF reader with a default configuration.
+    pub fn new(rdr: R) -> BgzfReader<R> {
+        BgzfReaderBuilder::new().build(rdr)
+    }
+
+    /// Return the virtual offset of the given absolute offset into the
+    /// uncompressed data handed out by this reader.
+    ///
+    /// Only offsets that fall within the most recently handed out blocks can
+    /// be translated. If `absolute_offset` is too far behind the data read so
+    /// far, or ahead of it, then `None` is returned. The absolute offset
+    /// corresponding to the end of the data handed out so far maps to the
+    /// start of the next block.
+    pub fn virtual_offset(&self, absolute_offset: u64) -> Option<VirtualOffset> {
+        let i = self
+            .index
+            .partition_point(|b| b.ustart + b.ulen <= absolute_offset);
+        if let Some(b) = self.index.get(i) {
+            if b.ustart > absolute_offset {
+                return None;
+            }
+            let within = u64::from(b.first) + (absolute_offset - b.ustart);
+            return Some(VirtualOffset::new(b.coffset, within as u16));
+        }
+        match self.index.back() {
+            Some(b) if b.ustart + b.ulen == absolute_offset => {
+                Some(VirtualOffset::new(b.coffset + b.clen, 0))
+            }
+            None if absolute_offset == self.delivered => {
+                Some(VirtualOffset::new(self.coffset, self.skip))
+            }
+            _ => None,
+        }
+    }
+
+    /// Read the next raw block from the underlying reader, returning `None`
+    /// at EOF.
+    fn read_raw_block(&mut self) -> io::Result<Option<Vec<u8>>> {
+        // The fixed gzip header plus XLEN.
+        let mut raw = vec![0; 12];
+        let mut len = 0;
+        while len < raw.len() {
+            match self.rdr.read(&mut raw[len..]) {
+                Ok(0) if len == 0 => return Ok(None),
+                Ok(0) => return Err(invalid_block("truncated block header")),
+                Ok(n) => len += n,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        if raw[..4] != [0x1F, 0x8B, 0x08, 0x04] {
+            return Err(invalid_block("not a BGZF block"));
+        }
+        let xlen = usize::from(u16::from_le_bytes([raw[10], raw[11]]));
+        raw.resize(12 + xlen, 0);
+        self.rdr.read_exact(&mut raw[12..])?;
+
+        // Find the BSIZE field among the extra subfields.
+        let mut bsize = None;
+        let mut extra = &raw[12..];
+        while extra.len() >= 4 {
+            let slen = usize::from(u16::from_le_bytes([extra[2], extra[3]]));
+            if extra[..2] == *b"BC" && slen == 2 && extra.len() >= 6 {
+                bsize = Some(usize::from(u16::from_le_bytes([extra[4], extra[5]])));
+                break;
+            }
+            extra = &extra[cmp::min(4 + slen, extra.len())..];
+        }
+        let total = match bsize {
+            Some(bsize) if bsize + 1 >= raw.len() + 8 => bsize + 1,
+            _ => return Err(invalid_block("missing or invalid BSIZE")),
+        };
+        let header_len = raw.len();
+        raw.resize(total, 0);
+        self.rdr.read_exact(&mut raw[header_len..])?;
+        Ok(Some(raw))
+    }
+
+    /// Queue raw blocks for decompression until enough are in flight.
+    fn queue_blocks(&mut self) -> io::Result<()> {
+        while !self.eof && self.pending.len() < self.max_in_flight {
+            let raw = match self.read_raw_block()? {
+                None => {
+                    self.eof = true;
+                    break;
+                }
+                Some(raw) => raw,
+            };
+            let (tx, rx) = mpsc::sync_channel(1);
+            let clen = raw.len() as u64;
+            let jobs = self.jobs.as_ref().expect("workers are running");
+            if jobs.send((raw, tx)).is_err() {
+                return Err(io::Error::other("BGZF worker threads have exited"));
+            }
+            self.pending.push_back(Pending {
+                coffset: self.coffset,
+                clen,
+                result: rx,
+            });
+            self.coffset += clen;
+        }
+        Ok(())
+    }
+
+    /// Make the next decompressed block current. Returns `false` at EOF.
+    fn next_block(&mut self) -> io::Result<bool> {
+        self.queue_blocks()?;
+        let pending = match self.pending.pop_front() {
+            None => return Ok(false),
+            Some(pending) => pending,
+        };
+        let block = match pending.result.recv() {
+            Ok(result) => result?,
+            Err(_) => return Err(io::Error::other("BGZF worker thread panicked")),
+        };
+        let first = std::mem::replace(&mut self.skip, 0);
+        if usize::from(first) > block.len() {
+            return Err(invalid_block("virtual offset is past the end of its block"));
+        }
+        self.block = block;
+        self.block_pos = usize::from(first);
+        self.index.push_back(BlockInfo {
+            coffset: pending.coffset,
+            clen: pending.clen,
+            ustart: self.delivered,
+            first,
+            ulen: (self.block.len() - self.block_pos) as u64,
+        });
+        while self.index.len() > MAX_INDEXED_BLOCKS {
+            self.index.pop_front();
+        }
+        Ok(true)
+    }
+}
+
+impl<R: io::Read + io::Seek> BgzfReader<R> {
+    /// Seek to the given virtual offset.
+    ///
+    /// Absolute offsets are counted from the seek point afterwards. That is,
+    /// the next byte read has absolute offset `0`.
+    pub fn seek_virtual(&mut self, offset: VirtualOffset) -> io::Result<()> {
+        // Blocks that are in flight are simply dropped. Their workers will
+        // notice that nobody is listening when they're done.
+        self.pending.clear();
+        self.rdr.seek(io::SeekFrom::Start(offset.compressed()))?;
+        self.coffset = offset.compressed();
+        self.eof = false;
+        self.block.clear();
+        self.block_pos = 0;
+        self.skip = offset.uncompressed();
+        self.delivered = 0;
+        self.index.clear();
+        Ok(())
+    }
+}
+
+impl<R: io::Read> io::Read for BgzfReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.block_pos == self.block.len() {
+            if !self.next_block()? {
+                return Ok(0);
+            }
+        }
+        let n = cmp::min(buf.len(), self.block.len() - self.block_pos);
+        buf[..n].copy_from_slice(&self.block[self.block_pos..self.block_pos + n]);
+        self.block_pos += n;
+        self.delivered += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R> Drop for BgzfReader<R> {
+    fn drop(&mut self) {
+        // Closing the job channel tells the workers to exit.
+        self.jobs.take();
+        self.pending.clear();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl<'b, R: io::Read> LineBufferReader<'b, BgzfReader<R>> {
+    /// The BGZF virtual offset which corresponds to `absolute_byte_offset`,
+    /// i.e., the start of the data returned by `buffer`.
+    ///
+    /// This returns `None` if the offset can no longer be determined, which
+    /// can only happen if the buffer holds more than the last 64 MB of data.
+    pub fn virtual_offset(&self) -> Option<VirtualOffset> {
+        self.get_ref().virtual_offset(self.absolute_byte_offset())
+    }
+}
+
+impl<'b, R: io::Read + io::Seek> LineBufferReader<'b, BgzfReader<R>> {
+    /// Seek to the given virtual offset and discard the contents of this
+    /// buffer.
+    ///
+    /// The virtual offset should point to the start of a line, e.g., one that
+    /// was previously returned by `virtual_offset`. Absolute offsets are
+    /// counted from the seek point afterwards.
+    pub fn seek_virtual(&mut self, offset: VirtualOffset) -> io::Result<()> {
+        self.get_mut().seek_virtual(offset)?;
+        self.reset(0);
+        Ok(())
+    }
+}
+
+/// Inflate a single raw BGZF block.
+fn inflate(raw: &[u8]) -> io::Result<Vec<u8>> {
+    let isize = u32::from_le_bytes([
+        raw[raw.len() - 4],
+        raw[raw.len() - 3],
+        raw[raw.len() - 2],
+        raw[raw.len() - 1],
+    ]);
+    let mut block = Vec::with_capacity(isize as usize);
+    flate2::read::GzDecoder::new(raw).read_to_end(&mut block)?;
+    Ok(block)
+}
+
+/// Inflate blocks until the job channel is closed.
+fn worker(jobs: Arc<Mutex<mpsc::Receiver<Job>>>) {
+    loop {
+        let job = match jobs.lock() {
+            Ok(jobs) => jobs.recv(),
+            Err(_) => return,
+        };
+        let (raw, result) = match job {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+        let _ = result.send(inflate(&raw));
+    }
+}
+
+fn invalid_block(msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid BGZF block: {}", msg),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_buffer::LineBufferBuilder;
+    use crate::lines::LineIter;
+    use crate::LineTerminator;
+    use std::io::{Cursor, Write};
+
+    /// Compress `data` into a single BGZF block.
+    fn block(data: &[u8]) -> Vec<u8> {
+        let mut enc = flate2::write::DeflateEncoder::new(vec![], flate2::Compression::default());
+        enc.write_all(data).unwrap();
+        let cdata = enc.finish().unwrap();
+        let mut crc = flate2::Crc::new();
+        crc.update(data);
+        let bsize = (18 + cdata.len() + 8 - 1) as u16;
+
+        let mut raw = vec![0x1F, 0x8B, 0x08, 0x04, 0, 0, 0, 0, 0, 0xFF, 6, 0];
+        raw.extend_from_slice(b"BC");
+        raw.extend_from_slice(&2u16.to_le_bytes());
+        raw.extend_from_slice(&bsize.to_le_bytes());
+        raw.extend_from_slice(&cdata);
+        raw.extend_from_slice(&crc.sum().to_le_bytes());
+        raw.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        raw
+    }
+
+    /// Compress `data` into BGZF blocks of `block_size` bytes, followed by
+    /// the empty EOF block.
+    fn bgzf(data: &[u8], block_size: usize) -> Vec<u8> {
+        let mut out = vec![];
+        for chunk in data.chunks(block_size) {
+            out.extend(block(chunk));
+        }
+        out.extend(block(b""));
+        out
+    }
+
+    const LINES: &str = "homer\nlisa\nmaggie\nbart\n";
+
+    #[test]
+    fn virtual_offset_parts() {
+        let offset = VirtualOffset::new(12345, 678);
+        assert_eq!(offset.compressed(), 12345);
+        assert_eq!(offset.uncompressed(), 678);
+        assert_eq!(u64::from(offset), (12345 << 16) | 678);
+    }
+
+    #[test]
+    fn read_in_order() {
+        let data = LINES.repeat(250);
+        let compressed = bgzf(data.as_bytes(), 37);
+        let mut rdr = BgzfReaderBuilder::new().threads(3).build(&compressed[..]);
+        let mut got = String::new();
+        rdr.read_to_string(&mut got).unwrap();
+        assert_eq!(got, data);
+    }
+
+    #[test]
+    fn invalid_block() {
+        let mut rdr = BgzfReader::new(&b"not a bgzf file"[..]);
+        let err = rdr.read(&mut [0; 16]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn seek_to_lines() {
+        let data = LINES.repeat(125);
+        let compressed = bgzf(data.as_bytes(), 50);
+        let mut linebuf = LineBufferBuilder::new().capacity(64).build();
+        let rdr = BgzfReaderBuilder::new()
+            .threads(2)
+            .build(Cursor::new(compressed));
+        let mut rdr = LineBufferReader::new(rdr, &mut linebuf);
+
+        // Record the virtual and uncompressed offsets of every line.
+        let mut offsets = vec![];
+        while rdr.fill().unwrap() {
+            let first = offsets.len();
+            let start = rdr.absolute_byte_offset();
+            let mut pos = 0;
+            for line in LineIter::new(LineTerminator::default(), rdr.buffer()) {
+                let offset = rdr.get_ref().virtual_offset(start + pos).unwrap();
+                offsets.push((offset, start + pos));
+                pos += line.len() as u64;
+            }
+            assert_eq!(rdr.virtual_offset(), Some(offsets[first].0));
+            rdr.consume_all();
+        }
+        assert_eq!(offsets.len(), 500);
+
+        // The lines repeat, so check everything after the seek.
+        for &i in [499, 0, 250, 251, 17].iter() {
+            let (offset, start) = offsets[i];
+            rdr.seek_virtual(offset).unwrap();
+            assert_eq!(rdr.virtual_offset(), Some(offset));
+            assert_eq!(rdr.absolute_byte_offset(), 0);
+            let mut rest = vec![];
+            while rdr.fill().unwrap() {
+                rest.extend_from_slice(rdr.buffer());
+                rdr.consume_all();
+            }
+            assert_eq!(rest, &data.as_bytes()[start as usize..], "line {}", i);
+        }
+    }
+}
@@ -0,0 +1,351 @@
+/*!
+Checkpoint the position of a line consumer and resume from it later.
+
+A `Checkpoint` records the absolute byte offset and line number of a
+`LineBufferReader`, along with its line terminator and a fingerprint of the
+bytes right before the offset. Resuming from a checkpoint seeks a new reader
+over the same input to the offset, after making sure those bytes are still
+the same. So an input that was truncated, rewritten or replaced since the
+checkpoint was taken is reported as `Error::CheckpointMismatch`, instead of
+reading on from a position that's no longer at the same line.
+
+With the `serde` feature enabled, checkpoints can be serialized.
+
+```no_run
+use ripline::line_buffer::{LineBufferBuilder, LineBufferReader};
+use std::fs::File;
+
+# fn example() -> Result<(), Box<dyn std::error::Error>> {
+let mut line_buffer = LineBufferBuilder::new().line_number(true).build();
+let mut lb_reader = LineBufferReader::new(File::open("big.tsv")?, &mut line_buffer);
+lb_reader.fill()?;
+lb_reader.consume_all();
+let checkpoint = lb_reader.checkpoint()?;
+
+// After a restart.
+let mut line_buffer = LineBufferBuilder::new().line_number(true).build();
+let file = File::open("big.tsv")?;
+let mut lb_reader = LineBufferReader::resume(file, &mut line_buffer, &checkpoint)?;
+while lb_reader.fill()? {
+    lb_reader.consume_all();
+}
+# Ok(())
+# }
+```
+*/
+
+use std::io::{self, Read, SeekFrom};
+
+use crate::line_buffer::{LineBuffer, LineBufferReader};
+use crate::Error;
+
+/// The maximum number of bytes before a checkpoint that are fingerprinted.
+const FINGERPRINT_LEN: u64 = 1 << 10; // 1 KB
+
+/// A position in the input of a line buffer reader that reading can be
+/// resumed from.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Checkpoint {
+    absolute_offset: u64,
+    line_number: Option<u64>,
+    line_terminator: u8,
+    fingerprint: u64,
+    fingerprint_len: u64,
+}
+
+impl Checkpoint {
+    /// The absolute byte offset reading resumes from.
+    pub fn absolute_offset(&self) -> u64 {
+        self.absolute_offset
+    }
+
+    /// The line number of the line at the checkpoint, if lines were being
+    /// counted.
+    pub fn line_number(&self) -> Option<u64> {
+        self.line_number
+    }
+
+    /// The line terminator of the line buffer the checkpoint was taken with.
+    pub fn line_terminator(&self) -> u8 {
+        self.line_terminator
+    }
+}
+
+impl<'b, R: io::Read + io::Seek> LineBufferReader<'b, R> {
+    /// Return a checkpoint at the start of the buffer, that is, right after
+    /// everything that has been consumed.
+    ///
+    /// The fingerprint is computed over up to 1 KB of input before the
+    /// checkpoint, which is read from the underlying reader. Its position is
+    /// restored afterwards, so reading carries on as if nothing happened.
+    /// This assumes that absolute byte offsets are offsets into the
+    /// underlying reader, which holds unless it was read from somewhere other
+    /// than its start without `seek_to` or `with_range`.
+    ///
+    /// I/O errors are returned as `Error::Io` at the offset of the
+    /// checkpoint.
+    pub fn checkpoint(&mut self) -> Result<Checkpoint, Error> {
+        let absolute_offset = self.absolute_byte_offset();
+        let line_number = self.line_number();
+        let line_terminator = self.line_terminator();
+        let rdr = self.get_mut();
+        let start = absolute_offset.saturating_sub(FINGERPRINT_LEN);
+        let bytes = rdr
+            .stream_position()
+            .and_then(|pos| {
+                let bytes = read_range(rdr, start, absolute_offset);
+                // Always seek back, or the next fill would read from wherever
+                // the failed read left off.
+                let restored = rdr.seek(SeekFrom::Start(pos));
+                let bytes = bytes?;
+                restored?;
+                Ok(bytes)
+            })
+            .map_err(|err| Error::Io {
+                err,
+                absolute_offset,
+                line_number,
+            })?;
+        Ok(Checkpoint {
+            absolute_offset,
+            line_number,
+            line_terminator,
+            fingerprint: fnv1a(&bytes),
+            fingerprint_len: bytes.len() as u64,
+        })
+    }
+
+    /// Create a new buffered reader over `rdr` that resumes reading at the
+    /// given checkpoint.
+    ///
+    /// This returns `Error::CheckpointMismatch` if the input doesn't match
+    /// the checkpoint, or if `line_buffer` has a different line terminator
+    /// than the checkpoint. If the checkpoint has a line number, then line
+    /// numbers carry on from it when they are enabled on `line_buffer`. I/O
+    /// errors are returned as `Error::Io` at the offset of the checkpoint.
+    pub fn resume(
+        mut rdr: R,
+        line_buffer: &'b mut LineBuffer,
+        checkpoint: &Checkpoint,
+    ) -> Result<LineBufferReader<'b, R>, Error> {
+        let offset = checkpoint.absolute_offset;
+        let io_error = |err| Error::Io {
+            err,
+            absolute_offset: offset,
+            line_number: checkpoint.line_number,
+        };
+        let mismatch = Error::CheckpointMismatch {
+            absolute_offset: offset,
+        };
+        if line_buffer.line_terminator() != checkpoint.line_terminator {
+            return Err(mismatch);
+        }
+        let start = match offset.checked_sub(checkpoint.fingerprint_len) {
+            Some(start) => start,
+            None => return Err(mismatch),
+        };
+        let bytes = read_range(&mut rdr, start, offset).map_err(io_error)?;
+        if bytes.len() as u64 != checkpoint.fingerprint_len
+            || fnv1a(&bytes) != checkpoint.fingerprint
+        {
+            return Err(mismatch);
+        }
+
+        let mut lb_reader = LineBufferReader::new(rdr, line_buffer);
+        lb_reader.seek_to(offset).map_err(io_error)?;
+        if let Some(line_number) = checkpoint.line_number {
+            lb_reader.set_line_number(line_number);
+        }
+        Ok(lb_reader)
+    }
+}
+
+/// Read `start..end` from `rdr`, or less if it ends before `end`.
+fn read_range<R: io::Read + io::Seek>(rdr: &mut R, start: u64, end: u64) -> io::Result<Vec<u8>> {
+    rdr.seek(SeekFrom::Start(start))?;
+    let mut bytes = Vec::with_capacity((end - start) as usize);
+    rdr.take(end - start).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Hash `bytes` with 64-bit FNV-1a.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_buffer::LineBufferBuilder;
+    use bstr::{ByteSlice, ByteVec};
+    use std::io::Cursor;
+
+    const LINES: &str = "homer\nlisa\nmaggie\nbart\n";
+
+    /// Consume `count` lines of `text` and return a checkpoint after them.
+    fn checkpoint_after(text: &str, count: usize) -> Checkpoint {
+        let mut linebuf = LineBufferBuilder::new()
+            .capacity(16)
+            .line_number(true)
+            .build();
+        let mut rdr = LineBufferReader::new(Cursor::new(text), &mut linebuf);
+        let mut consumed = 0;
+        while consumed < count && rdr.fill().unwrap() {
+            let len = rdr.buffer().find_byte(b'\n').unwrap() + 1;
+            rdr.consume(len);
+            consumed += 1;
+        }
+        let checkpoint = rdr.checkpoint().unwrap();
+        // Taking a checkpoint doesn't disturb reading.
+        let mut rest = vec![];
+        while rdr.fill().unwrap() {
+            rest.push_str(rdr.buffer());
+            rdr.consume_all();
+        }
+        assert_eq!(
+            rest.len() as u64,
+            text.len() as u64 - checkpoint.absolute_offset()
+        );
+        checkpoint
+    }
+
+    fn resume(text: &str, checkpoint: &Checkpoint) -> Result<(Option<u64>, String), Error> {
+        let mut linebuf = LineBufferBuilder::new().line_number(true).build();
+        let mut rdr = LineBufferReader::resume(Cursor::new(text), &mut linebuf, checkpoint)?;
+        let line_number = rdr.line_number();
+        let mut rest = vec![];
+        while rdr.fill()? {
+            rest.push_str(rdr.buffer());
+            rdr.consume_all();
+        }
+        Ok((line_number, rest.to_str().unwrap().to_string()))
+    }
+
+    #[test]
+    fn checkpoint_resume() {
+        let text = LINES.repeat(125);
+        for &count in [0, 1, 17, 499, 500].iter() {
+            let checkpoint = checkpoint_after(&text, count);
+            assert_eq!(checkpoint.line_number(), Some(count as u64 + 1));
+            let offset = checkpoint.absolute_offset() as usize;
+            let (line_number, rest) = resume(&text, &checkpoint).unwrap();
+            assert_eq!(line_number, Some(count as u64 + 1));
+            assert_eq!(rest, &text[offset..]);
+        }
+    }
+
+    #[test]
+    fn checkpoint_mismatch() {
+        let text = LINES.repeat(125);
+        let checkpoint = checkpoint_after(&text, 200);
+
+        // Change a byte of the line right before the checkpoint.
+        let mut changed = text.clone().into_bytes();
+        changed[checkpoint.absolute_offset() as usize - 2] = b'x';
+        let changed = String::from_utf8(changed).unwrap();
+        let truncated = &text[..1000];
+        let shifted = format!("\n{}", text);
+        for input in [&changed[..], truncated, &shifted[..]].iter() {
+            match resume(input, &checkpoint) {
+                Err(Error::CheckpointMismatch { absolute_offset }) => {
+                    assert_eq!(absolute_offset, checkpoint.absolute_offset());
+                }
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+
+        // Appending to the input is fine.
+        let appended = format!("{}more\n", text);
+        let (_, rest) = resume(&appended, &checkpoint).unwrap();
+        assert!(rest.ends_with("bart\nmore\n"));
+
+        let mut linebuf = LineBufferBuilder::new().line_terminator(b'\0').build();
+        let err = LineBufferReader::resume(Cursor::new(&text), &mut linebuf, &checkpoint);
+        assert!(matches!(err, Err(Error::CheckpointMismatch { .. })));
+    }
+
+    #[test]
+    fn checkpoint_io_error() {
+        /// A reader whose seeks or reads fail on demand.
+        struct Faulty<R> {
+            rdr: R,
+            fail_seek: bool,
+            fail_read: bool,
+        }
+
+        impl<R: io::Read> io::Read for Faulty<R> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                if self.fail_read {
+                    return Err(io::Error::new(io::ErrorKind::BrokenPipe, "no reading"));
+                }
+                self.rdr.read(buf)
+            }
+        }
+
+        impl<R: io::Seek> io::Seek for Faulty<R> {
+            fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+                if self.fail_seek {
+                    return Err(io::Error::new(io::ErrorKind::Unsupported, "no seeking"));
+                }
+                self.rdr.seek(pos)
+            }
+        }
+
+        let text = "homer\nlisa\nmaggie\nbart\nmarge\n";
+        for &(fail_seek, kind) in [
+            (true, io::ErrorKind::Unsupported),
+            (false, io::ErrorKind::BrokenPipe),
+        ]
+        .iter()
+        {
+            let faulty = Faulty {
+                rdr: Cursor::new(text),
+                fail_seek: false,
+                fail_read: false,
+            };
+            let mut linebuf = LineBufferBuilder::new()
+                .capacity(8)
+                .line_number(true)
+                .build();
+            let mut rdr = LineBufferReader::new(faulty, &mut linebuf);
+            rdr.fill().unwrap();
+            rdr.consume(6);
+            let pos = rdr.get_ref().rdr.position();
+            rdr.get_mut().fail_seek = fail_seek;
+            rdr.get_mut().fail_read = !fail_seek;
+            match rdr.checkpoint() {
+                Err(Error::Io {
+                    err,
+                    absolute_offset: 6,
+                    line_number: Some(2),
+                }) => assert_eq!(err.kind(), kind),
+                other => panic!("unexpected result: {:?}", other),
+            }
+
+            // A failed read leaves the reader where it was, so reading
+            // carries on as if nothing happened.
+            assert_eq!(rdr.get_ref().rdr.position(), pos);
+            rdr.get_mut().fail_read = false;
+            let mut rest = vec![];
+            while rdr.fill().unwrap() {
+                rest.push_str(rdr.buffer());
+                rdr.consume_all();
+            }
+            assert_eq!(rest, &text.as_bytes()[6..]);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn checkpoint_serde() {
+        let checkpoint = checkpoint_after(&LINES.repeat(3), 3);
+        let json = serde_json::to_string(&checkpoint).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Checkpoint>(&json).unwrap(),
+            checkpoint
+        );
+    }
+}
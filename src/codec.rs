@@ -0,0 +1,282 @@
+/*!
+A `tokio_util::codec` decoder and encoder for line framed transports.
+
+Lines are cut from the read buffer with `LineStep`, following the same
+`LineTerminator` rules as the rest of this crate.
+
+```
+use bytes::BytesMut;
+use ripline::{codec::LineCodecBuilder, line_buffer::BufferAllocation};
+use tokio_util::codec::Decoder;
+
+let mut codec = LineCodecBuilder::new()
+    .buffer_alloc(BufferAllocation::Error(1 << 20))
+    .build();
+let mut buf = BytesMut::from(&b"homer\nlisa\nmag"[..]);
+assert_eq!(codec.decode(&mut buf).unwrap().unwrap(), "homer\n");
+assert_eq!(codec.decode(&mut buf).unwrap().unwrap(), "lisa\n");
+assert_eq!(codec.decode(&mut buf).unwrap(), None);
+```
+
+In practice, the codec is usually handed to `tokio_util::codec::FramedRead`
+or `FramedWrite`.
+*/
+
+use std::io;
+
+use bytes::{BufMut, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::line_buffer::BufferAllocation;
+use crate::lines::{without_terminator, LineStep};
+use crate::{Error, LineTerminator};
+
+/// A builder for constructing line codecs.
+#[derive(Clone, Debug, Default)]
+pub struct LineCodecBuilder {
+    line_term: LineTerminator,
+    buffer_alloc: BufferAllocation,
+    strip: bool,
+}
+
+impl LineCodecBuilder {
+    /// Create a new builder for a line codec.
+    pub fn new() -> LineCodecBuilder {
+        LineCodecBuilder::default()
+    }
+
+    /// Create a new line codec from this builder's configuration.
+    pub fn build(&self) -> LineCodec {
+        LineCodec {
+            line_term: self.line_term,
+            buffer_alloc: self.buffer_alloc,
+            strip: self.strip,
+            next_index: 0,
+            absolute_offset: 0,
+        }
+    }
+
+    /// Set the line terminator used to split and write lines.
+    ///
+    /// By default, this is set to `b'\n'`.
+    pub fn line_terminator(&mut self, line_term: LineTerminator) -> &mut LineCodecBuilder {
+        self.line_term = line_term;
+        self
+    }
+
+    /// Set the maximum length of a line.
+    ///
+    /// With `BufferAllocation::Error(limit)`, decoding fails with an error
+    /// as soon as a line longer than `limit` bytes, not counting its
+    /// terminator, is seen, without waiting for the rest of the line to
+    /// arrive. By default, this is set to `BufferAllocation::Eager`, which
+    /// puts no limit on the length of a line.
+    ///
+    /// This codec can't cut or drop lines, so `BufferAllocation::Truncate`,
+    /// `BufferAllocation::Skip` and `BufferAllocation::Fragment` behave like
+    /// `BufferAllocation::Error` with the same limit.
+    pub fn buffer_alloc(&mut self, behavior: BufferAllocation) -> &mut LineCodecBuilder {
+        self.buffer_alloc = behavior;
+        self
+    }
+
+    /// When enabled, decoded lines don't include their line terminator.
+    ///
+    /// This is disabled by default.
+    pub fn strip_terminator(&mut self, yes: bool) -> &mut LineCodecBuilder {
+        self.strip = yes;
+        self
+    }
+}
+
+/// A codec that splits a byte stream into lines, and writes lines followed by
+/// a line terminator.
+#[derive(Clone, Debug)]
+pub struct LineCodec {
+    line_term: LineTerminator,
+    buffer_alloc: BufferAllocation,
+    strip: bool,
+    /// The index in the read buffer up to which we know there is no line
+    /// terminator, so that we don't search the same bytes twice.
+    next_index: usize,
+    /// The absolute byte offset of the start of the read buffer, for error
+    /// reporting.
+    absolute_offset: u64,
+}
+
+impl Default for LineCodec {
+    fn default() -> LineCodec {
+        LineCodecBuilder::new().build()
+    }
+}
+
+impl LineCodec {
+    /// Split the line that ends at `end` off the front of `buf`.
+    fn take_line(&mut self, buf: &mut BytesMut, end: usize) -> BytesMut {
+        self.next_index = 0;
+        self.absolute_offset += end as u64;
+        let mut line = buf.split_to(end);
+        if self.strip {
+            let len = without_terminator(&line, self.line_term).len();
+            line.truncate(len);
+        }
+        line
+    }
+
+    /// Return an error if `len` bytes are more than a line may hold.
+    fn check_len(&self, len: usize) -> Result<(), io::Error> {
+        match self.buffer_alloc {
+            BufferAllocation::Error(limit)
+            | BufferAllocation::Truncate(limit)
+            | BufferAllocation::Skip(limit)
+            | BufferAllocation::Fragment(limit)
+                if len > limit =>
+            {
+                Err(Error::AllocationLimit {
+                    limit,
+                    absolute_offset: self.absolute_offset,
+                }
+                .into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Decoder for LineCodec {
+    type Item = BytesMut;
+    type Error = io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<BytesMut>, io::Error> {
+        let mut step = LineStep::new(self.line_term, self.next_index, buf.len());
+        match step.next(buf) {
+            Some((_, end)) if self.line_term.is_suffix(&buf[..end]) => {
+                self.check_len(without_terminator(&buf[..end], self.line_term).len())?;
+                Ok(Some(self.take_line(buf, end)))
+            }
+            _ => {
+                // With CRLF, a trailing `\r` may turn out to be part of the
+                // terminator, so don't count it against the limit yet.
+                let mut len = buf.len();
+                if self.line_term.is_crlf() && buf.ends_with(b"\r") {
+                    len -= 1;
+                }
+                self.check_len(len)?;
+                self.next_index = buf.len();
+                Ok(None)
+            }
+        }
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> Result<Option<BytesMut>, io::Error> {
+        if let Some(line) = self.decode(buf)? {
+            return Ok(Some(line));
+        }
+        if buf.is_empty() {
+            return Ok(None);
+        }
+        let end = buf.len();
+        Ok(Some(self.take_line(buf, end)))
+    }
+}
+
+impl<T: AsRef<[u8]>> Encoder<T> for LineCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, line: T, buf: &mut BytesMut) -> Result<(), io::Error> {
+        let line = line.as_ref();
+        let line_term = self.line_term.as_bytes();
+        buf.reserve(line.len() + line_term.len());
+        buf.put_slice(line);
+        buf.put_slice(line_term);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_all(codec: &mut LineCodec, input: &[u8]) -> Vec<BytesMut> {
+        let mut buf = BytesMut::new();
+        let mut lines = vec![];
+        // Feed the input one byte at a time to exercise partial lines.
+        for &b in input {
+            buf.put_u8(b);
+            while let Some(line) = codec.decode(&mut buf).unwrap() {
+                lines.push(line);
+            }
+        }
+        while let Some(line) = codec.decode_eof(&mut buf).unwrap() {
+            lines.push(line);
+        }
+        lines
+    }
+
+    #[test]
+    fn decode_lines() {
+        let mut codec = LineCodec::default();
+        let lines = decode_all(&mut codec, b"homer\nlisa\n\nmaggie");
+        assert_eq!(lines, vec!["homer\n", "lisa\n", "\n", "maggie"]);
+    }
+
+    #[test]
+    fn decode_crlf_stripped() {
+        let mut codec = LineCodecBuilder::new()
+            .line_terminator(LineTerminator::crlf())
+            .strip_terminator(true)
+            .build();
+        let lines = decode_all(&mut codec, b"homer\r\nlisa\nmaggie\r\n");
+        assert_eq!(lines, vec!["homer", "lisa", "maggie"]);
+    }
+
+    #[test]
+    fn decode_limit() {
+        let mut codec = LineCodecBuilder::new()
+            .buffer_alloc(BufferAllocation::Error(4))
+            .build();
+        let mut buf = BytesMut::from(&b"lisa\nhom"[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap().unwrap(), "lisa\n");
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        buf.put_slice(b"er");
+        assert!(codec.decode(&mut buf).is_err());
+    }
+
+    #[test]
+    fn decode_limit_other_allocations() {
+        let allocs = [
+            BufferAllocation::Truncate(4),
+            BufferAllocation::Skip(4),
+            BufferAllocation::Fragment(4),
+        ];
+        for &alloc in allocs.iter() {
+            let mut codec = LineCodecBuilder::new().buffer_alloc(alloc).build();
+            let mut buf = BytesMut::from(&b"lisa\nhomer\n"[..]);
+            assert_eq!(codec.decode(&mut buf).unwrap().unwrap(), "lisa\n");
+            assert!(codec.decode(&mut buf).is_err());
+        }
+    }
+
+    #[test]
+    fn decode_limit_crlf() {
+        let mut codec = LineCodecBuilder::new()
+            .line_terminator(LineTerminator::crlf())
+            .buffer_alloc(BufferAllocation::Error(4))
+            .build();
+        let mut buf = BytesMut::from(&b"lisa\r"[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        buf.put_slice(b"\n");
+        assert_eq!(codec.decode(&mut buf).unwrap().unwrap(), "lisa\r\n");
+    }
+
+    #[test]
+    fn encode_lines() {
+        let mut codec = LineCodecBuilder::new()
+            .line_terminator(LineTerminator::crlf())
+            .build();
+        let mut buf = BytesMut::new();
+        codec.encode("homer", &mut buf).unwrap();
+        codec.encode(b"lisa", &mut buf).unwrap();
+        assert_eq!(buf, "homer\r\nlisa\r\n");
+    }
+}
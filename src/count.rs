@@ -0,0 +1,347 @@
+/*!
+Count the lines of a file or reader, like `wc -l`.
+
+A final line that isn't followed by a line terminator is counted as a line.
+With the `parallel` feature enabled, large regular files are memory mapped and
+counted on multiple threads. Everything else, like pipes, is counted by
+streaming it through a `LineBufferReader`.
+
+```no_run
+use ripline::count::LineCounterBuilder;
+
+# fn example() -> std::io::Result<()> {
+let count = LineCounterBuilder::new().longest_line(true).build().count_path("big.tsv")?;
+println!("{} lines, {} bytes", count.lines(), count.bytes());
+# Ok(())
+# }
+```
+*/
+
+use std::cmp;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use crate::line_buffer::{
+    BufferAllocation, Fragment, LineBuffer, LineBufferBuilder, LineBufferReader,
+    DEFAULT_BUFFER_CAPACITY,
+};
+use crate::lines::{self, without_terminator, LineIter};
+use crate::LineTerminator;
+
+/// Regular files smaller than this are streamed instead of memory mapped,
+/// since setting up the map and the threads isn't worth it.
+#[cfg(feature = "parallel")]
+const MMAP_THRESHOLD: u64 = 1 << 20; // 1 MB
+
+/// The result of counting lines.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct LineCount {
+    lines: u64,
+    bytes: u64,
+    longest_line: Option<u64>,
+}
+
+impl LineCount {
+    /// The number of lines.
+    pub fn lines(&self) -> u64 {
+        self.lines
+    }
+
+    /// The number of bytes.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// The length in bytes of the longest line, not counting its line
+    /// terminator.
+    ///
+    /// This is only computed when enabled with
+    /// `LineCounterBuilder::longest_line`, and is `None` otherwise.
+    pub fn longest_line(&self) -> Option<u64> {
+        self.longest_line
+    }
+
+    /// Combine the counts of two consecutive pieces of input that both start
+    /// at a line boundary. Partial final lines are accounted for separately.
+    fn merge(self, other: LineCount) -> LineCount {
+        LineCount {
+            lines: self.lines + other.lines,
+            bytes: self.bytes + other.bytes,
+            longest_line: cmp::max(self.longest_line, other.longest_line),
+        }
+    }
+}
+
+/// A builder for constructing line counters.
+#[derive(Clone, Debug, Default)]
+pub struct LineCounterBuilder {
+    line_term: LineTerminator,
+    longest_line: bool,
+}
+
+impl LineCounterBuilder {
+    /// Create a new builder for a line counter.
+    pub fn new() -> LineCounterBuilder {
+        LineCounterBuilder::default()
+    }
+
+    /// Create a new line counter from this builder's configuration.
+    pub fn build(&self) -> LineCounter {
+        LineCounter {
+            line_term: self.line_term,
+            longest_line: self.longest_line,
+        }
+    }
+
+    /// Set the line terminator used to count lines.
+    ///
+    /// Since a CRLF terminated line also ends with `\n`, this only makes a
+    /// difference for the length of the longest line.
+    ///
+    /// By default, this is set to `b'\n'`.
+    pub fn line_terminator(&mut self, line_term: LineTerminator) -> &mut LineCounterBuilder {
+        self.line_term = line_term;
+        self
+    }
+
+    /// When enabled, also compute the length of the longest line.
+    ///
+    /// This requires looking at every line instead of just counting line
+    /// terminators, so it is disabled by default.
+    pub fn longest_line(&mut self, yes: bool) -> &mut LineCounterBuilder {
+        self.longest_line = yes;
+        self
+    }
+}
+
+/// Counts lines in files, readers and slices.
+#[derive(Clone, Debug, Default)]
+pub struct LineCounter {
+    line_term: LineTerminator,
+    longest_line: bool,
+}
+
+impl LineCounter {
+    /// Create a new line counter with a default configuration.
+    pub fn new() -> LineCounter {
+        LineCounterBuilder::new().build()
+    }
+
+    /// Count the lines in the file at the given path.
+    ///
+    /// With the `parallel` feature enabled, large regular files are memory
+    /// mapped and counted in parallel. If the file is modified while it is
+    /// being counted, the result is unspecified.
+    pub fn count_path<P: AsRef<Path>>(&self, path: P) -> io::Result<LineCount> {
+        let file = File::open(path)?;
+        #[cfg(feature = "parallel")]
+        {
+            let md = file.metadata()?;
+            if md.is_file() && md.len() >= MMAP_THRESHOLD {
+                // SAFETY: we only read the map, and document that concurrent
+                // modification leads to unspecified counts.
+                let mmap = unsafe { memmap2::Mmap::map(&file)? };
+                return Ok(self.count_slice_parallel(&mmap));
+            }
+        }
+        self.count_reader(file)
+    }
+
+    /// Count the lines produced by the given reader.
+    ///
+    /// This runs in constant memory: lines that don't fit in the buffer are
+    /// counted piece by piece instead of being buffered in full.
+    pub fn count_reader<R: io::Read>(&self, rdr: R) -> io::Result<LineCount> {
+        let mut line_buffer = self.line_buffer(DEFAULT_BUFFER_CAPACITY);
+        self.count_buffered(&mut LineBufferReader::new(rdr, &mut line_buffer))
+    }
+
+    /// Return a line buffer that hands out lines longer than `capacity` in
+    /// pieces.
+    fn line_buffer(&self, capacity: usize) -> LineBuffer {
+        LineBufferBuilder::new()
+            .capacity(capacity)
+            .line_terminator(self.line_term.as_byte())
+            .buffer_alloc(BufferAllocation::Fragment(capacity))
+            .build()
+    }
+
+    /// Count the lines produced by `lb_reader`, which must use a line buffer
+    /// from `line_buffer`.
+    fn count_buffered<R: io::Read>(
+        &self,
+        lb_reader: &mut LineBufferReader<'_, R>,
+    ) -> io::Result<LineCount> {
+        let mut count = self.empty();
+        let mut last = None;
+        // The length of the pieces of an oversized line seen so far.
+        let mut partial = 0;
+        while lb_reader.fill()? {
+            let buf = lb_reader.buffer();
+            match lb_reader.fragment() {
+                None => count = count.merge(self.count_lines(buf)),
+                Some(fragment) => {
+                    count.lines += lines::count(buf, self.line_term);
+                    count.bytes += buf.len() as u64;
+                    partial += buf.len() as u64;
+                    if fragment == Fragment::Last {
+                        if self.longest_line {
+                            let mut term_len =
+                                buf.len() - without_terminator(buf, self.line_term).len();
+                            // The `\r` of a CRLF may have ended the previous
+                            // piece.
+                            if self.line_term.is_crlf() && buf == b"\n" && last == Some(b'\r') {
+                                term_len += 1;
+                            }
+                            let len = partial - term_len as u64;
+                            count.longest_line = cmp::max(count.longest_line, Some(len));
+                        }
+                        partial = 0;
+                    }
+                }
+            }
+            last = buf.last().copied();
+            lb_reader.consume_all();
+        }
+        Ok(self.finish(count, last))
+    }
+
+    /// Count the lines in the given slice.
+    pub fn count_slice(&self, bytes: &[u8]) -> LineCount {
+        self.finish(self.count_lines(bytes), bytes.last().copied())
+    }
+
+    /// Count the lines in the given slice on multiple threads.
+    #[cfg(feature = "parallel")]
+    pub fn count_slice_parallel(&self, bytes: &[u8]) -> LineCount {
+        use rayon::prelude::*;
+
+        let chunks = rayon::current_num_threads() * 4;
+        let count = crate::parallel::chunk_ranges(bytes, self.line_term, chunks)
+            .into_par_iter()
+            .map(|range| self.count_lines(&bytes[range]))
+            .reduce(|| self.empty(), LineCount::merge);
+        self.finish(count, bytes.last().copied())
+    }
+
+    /// Return the count of empty input.
+    fn empty(&self) -> LineCount {
+        LineCount {
+            longest_line: if self.longest_line { Some(0) } else { None },
+            ..LineCount::default()
+        }
+    }
+
+    /// Count the line terminators in `bytes`, which starts at a line
+    /// boundary, and the longest line if enabled.
+    fn count_lines(&self, bytes: &[u8]) -> LineCount {
+        let longest_line = if self.longest_line {
+            LineIter::new(self.line_term, bytes)
+                .map(|line| without_terminator(line, self.line_term).len() as u64)
+                .max()
+                .or(Some(0))
+        } else {
+            None
+        };
+        LineCount {
+            lines: lines::count(bytes, self.line_term),
+            bytes: bytes.len() as u64,
+            longest_line,
+        }
+    }
+
+    /// Account for a final line without a line terminator, given the last
+    /// byte of the input.
+    fn finish(&self, mut count: LineCount, last: Option<u8>) -> LineCount {
+        if last.is_some_and(|b| b != self.line_term.as_byte()) {
+            count.lines += 1;
+        }
+        count
+    }
+}
+
+/// Count the lines in the file at the given path with a default
+/// configuration.
+///
+/// This is a convenience function for `LineCounter::new().count_path(path)`.
+pub fn count_path<P: AsRef<Path>>(path: P) -> io::Result<LineCount> {
+    LineCounter::new().count_path(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counter(line_term: LineTerminator) -> LineCounter {
+        LineCounterBuilder::new()
+            .line_terminator(line_term)
+            .longest_line(true)
+            .build()
+    }
+
+    fn count(line_term: LineTerminator, bytes: &[u8]) -> (u64, u64, Option<u64>) {
+        let counter = counter(line_term);
+        let count = counter.count_slice(bytes);
+        assert_eq!(counter.count_reader(bytes).unwrap(), count);
+        #[cfg(feature = "parallel")]
+        assert_eq!(counter.count_slice_parallel(bytes), count);
+        (count.lines(), count.bytes(), count.longest_line())
+    }
+
+    #[test]
+    fn count_basics() {
+        let lf = LineTerminator::default();
+        assert_eq!(count(lf, b""), (0, 0, Some(0)));
+        assert_eq!(count(lf, b"\n"), (1, 1, Some(0)));
+        assert_eq!(count(lf, b"homer\nlisa\n"), (2, 11, Some(5)));
+        assert_eq!(count(lf, b"homer\nlisa\nmaggie"), (3, 17, Some(6)));
+    }
+
+    #[test]
+    fn count_crlf() {
+        let crlf = LineTerminator::crlf();
+        assert_eq!(count(crlf, b"homer\r\nlisa\r\n"), (2, 13, Some(5)));
+        assert_eq!(count(crlf, b"homer\r\nlisa\nmaggie\r"), (3, 19, Some(7)));
+    }
+
+    #[test]
+    fn count_without_longest_line() {
+        let count = LineCounter::new().count_slice(b"homer\nlisa");
+        assert_eq!(count.lines(), 2);
+        assert_eq!(count.longest_line(), None);
+    }
+
+    #[test]
+    fn count_long_lines() {
+        for &line_term in [LineTerminator::default(), LineTerminator::crlf()].iter() {
+            let long = "x".repeat(10_000);
+            let text = format!("homer\r\n{}\r\nlisa\n{}", long, long);
+            let counter = counter(line_term);
+            let expected = counter.count_slice(text.as_bytes());
+            for &capacity in [1, 7, 64].iter() {
+                let mut line_buffer = counter.line_buffer(capacity);
+                let mut rdr = LineBufferReader::new(text.as_bytes(), &mut line_buffer);
+                assert_eq!(counter.count_buffered(&mut rdr).unwrap(), expected);
+                assert!(
+                    rdr.high_water_mark() <= 4 * capacity + 16,
+                    "capacity {}",
+                    capacity
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn count_file() {
+        let path = std::env::temp_dir().join(format!("ripline-count-{}", std::process::id()));
+        // Large enough to be memory mapped with the `parallel` feature.
+        let text: String = (0..200_000).map(|i| format!("line {}\n", i)).collect();
+        std::fs::write(&path, format!("{}partial", text)).unwrap();
+        let count = count_path(&path);
+        std::fs::remove_file(&path).unwrap();
+        let count = count.unwrap();
+        assert_eq!(count.lines(), 200_001);
+        assert_eq!(count.bytes(), text.len() as u64 + 7);
+    }
+}
@@ -0,0 +1,327 @@
+/*!
+Transparent decompression of line oriented inputs.
+
+A `DecompressionReader` wraps any `io::Read` and sniffs the magic bytes at
+the start of the stream on the first read. If they match a compression format
+whose cargo feature is enabled, then the stream is decoded on the fly.
+Otherwise, the bytes are passed through unchanged.
+
+`LineBufferReader::with_decompression` does the wrapping for you, so the
+magic bytes are sniffed in the first fill.
+
+```no_run
+use ripline::line_buffer::{LineBufferBuilder, LineBufferReader};
+use std::fs::File;
+
+# fn example() -> Result<(), Box<dyn std::error::Error>> {
+let mut line_buffer = LineBufferBuilder::new().build();
+let mut lb_reader =
+    LineBufferReader::with_decompression(File::open("lines.txt.gz")?, &mut line_buffer);
+while lb_reader.fill()? {
+    lb_reader.consume_all();
+}
+# Ok(())
+# }
+```
+*/
+
+use crate::line_buffer::{LineBuffer, LineBufferReader};
+use std::io;
+
+/// The number of bytes needed to recognize every supported format.
+const MAGIC_LEN: usize = 6;
+
+/// A compression format recognized by a `DecompressionReader`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum CompressionFormat {
+    /// gzip, including multi-member and BGZF files. Requires the `gzip`
+    /// feature.
+    Gzip,
+    /// Zstandard. Requires the `zstd` feature.
+    Zstd,
+    /// bzip2. Requires the `bzip2` feature.
+    Bzip2,
+    /// xz. Requires the `xz` feature.
+    Xz,
+}
+
+impl CompressionFormat {
+    /// Detect the compression format from the leading bytes of a stream.
+    ///
+    /// Only formats whose feature is enabled are ever returned.
+    pub fn detect(bytes: &[u8]) -> Option<CompressionFormat> {
+        #[cfg(feature = "gzip")]
+        {
+            if bytes.starts_with(&[0x1F, 0x8B, 0x08]) {
+                return Some(CompressionFormat::Gzip);
+            }
+        }
+        #[cfg(feature = "zstd")]
+        {
+            if bytes.starts_with(&[0x28, 0xB5, 0x2F, 0xFD]) {
+                return Some(CompressionFormat::Zstd);
+            }
+        }
+        #[cfg(feature = "bzip2")]
+        {
+            if bytes.len() >= 4 && bytes.starts_with(b"BZh") && (b'1'..=b'9').contains(&bytes[3]) {
+                return Some(CompressionFormat::Bzip2);
+            }
+        }
+        #[cfg(feature = "xz")]
+        {
+            if bytes.starts_with(&[0xFD, b'7', b'z', b'X', b'Z', 0x00]) {
+                return Some(CompressionFormat::Xz);
+            }
+        }
+        None
+    }
+}
+
+/// The bytes that were read while sniffing, followed by the rest of the
+/// underlying reader.
+type Prefixed<R> = io::Chain<io::Cursor<Vec<u8>>, R>;
+
+/// The state of a decompression reader.
+enum Inner<R: io::Read> {
+    /// The magic bytes haven't been read in full yet.
+    Sniffing(R, Vec<u8>),
+    /// Temporarily occupied while switching states.
+    Empty,
+    /// Setting up the decoder failed with an error of the given kind and
+    /// message, which is returned by every read.
+    Failed(io::ErrorKind, String),
+    Plain(Prefixed<R>),
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::read::MultiGzDecoder<Prefixed<R>>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::read::Decoder<'static, io::BufReader<Prefixed<R>>>),
+    #[cfg(feature = "bzip2")]
+    Bzip2(bzip2::read::MultiBzDecoder<Prefixed<R>>),
+    #[cfg(feature = "xz")]
+    Xz(xz2::read::XzDecoder<Prefixed<R>>),
+}
+
+/// A reader that transparently decompresses its input.
+///
+/// The compression format is detected from magic bytes when this reader is
+/// first read from, which typically happens on the first call to
+/// `LineBufferReader::fill`. Inputs that aren't recognized are passed through
+/// as is.
+pub struct DecompressionReader<R: io::Read> {
+    inner: Inner<R>,
+    format: Option<CompressionFormat>,
+}
+
+impl<R: io::Read> DecompressionReader<R> {
+    /// Create a new reader that decompresses the contents of `rdr`, if they
+    /// are compressed.
+    pub fn new(rdr: R) -> DecompressionReader<R> {
+        DecompressionReader {
+            inner: Inner::Sniffing(rdr, Vec::with_capacity(MAGIC_LEN)),
+            format: None,
+        }
+    }
+
+    /// The compression format that was detected, if any.
+    ///
+    /// This always returns `None` before the first read.
+    pub fn format(&self) -> Option<CompressionFormat> {
+        self.format
+    }
+
+    /// Read the magic bytes from the underlying reader and wrap it in the
+    /// matching decoder.
+    fn sniff(&mut self) -> io::Result<()> {
+        let (mut rdr, mut magic) = match std::mem::replace(&mut self.inner, Inner::Empty) {
+            Inner::Sniffing(rdr, magic) => (rdr, magic),
+            inner => {
+                self.inner = inner;
+                return Ok(());
+            }
+        };
+        let mut buf = [0; MAGIC_LEN];
+        while magic.len() < MAGIC_LEN {
+            match rdr.read(&mut buf[..MAGIC_LEN - magic.len()]) {
+                Ok(0) => break,
+                Ok(n) => magic.extend_from_slice(&buf[..n]),
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    // Keep what we have so that sniffing resumes on the next
+                    // read.
+                    self.inner = Inner::Sniffing(rdr, magic);
+                    return Err(err);
+                }
+            }
+        }
+        self.format = CompressionFormat::detect(&magic);
+        let rdr = io::Read::chain(io::Cursor::new(magic), rdr);
+        let inner: io::Result<Inner<R>> = match self.format {
+            None => Ok(Inner::Plain(rdr)),
+            #[cfg(feature = "gzip")]
+            Some(CompressionFormat::Gzip) => {
+                Ok(Inner::Gzip(flate2::read::MultiGzDecoder::new(rdr)))
+            }
+            #[cfg(feature = "zstd")]
+            Some(CompressionFormat::Zstd) => zstd::stream::read::Decoder::new(rdr).map(Inner::Zstd),
+            #[cfg(feature = "bzip2")]
+            Some(CompressionFormat::Bzip2) => {
+                Ok(Inner::Bzip2(bzip2::read::MultiBzDecoder::new(rdr)))
+            }
+            #[cfg(feature = "xz")]
+            Some(CompressionFormat::Xz) => {
+                Ok(Inner::Xz(xz2::read::XzDecoder::new_multi_decoder(rdr)))
+            }
+            #[allow(unreachable_patterns)]
+            Some(_) => unreachable!("detected a format whose feature is disabled"),
+        };
+        match inner {
+            Ok(inner) => {
+                self.inner = inner;
+                Ok(())
+            }
+            Err(err) => {
+                // The underlying reader is gone with the decoder, so there's
+                // no way to recover.
+                self.inner = Inner::Failed(err.kind(), err.to_string());
+                Err(err)
+            }
+        }
+    }
+}
+
+impl<R: io::Read> io::Read for DecompressionReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Inner::Sniffing(..) = self.inner {
+            self.sniff()?;
+        }
+        match self.inner {
+            // Sniffing only stays on after an error that it returned.
+            Inner::Sniffing(..) | Inner::Empty => Err(io::Error::other(
+                "decompression reader is in an invalid state",
+            )),
+            Inner::Failed(kind, ref msg) => Err(io::Error::new(kind, msg.clone())),
+            Inner::Plain(ref mut rdr) => rdr.read(buf),
+            #[cfg(feature = "gzip")]
+            Inner::Gzip(ref mut rdr) => rdr.read(buf),
+            #[cfg(feature = "zstd")]
+            Inner::Zstd(ref mut rdr) => rdr.read(buf),
+            #[cfg(feature = "bzip2")]
+            Inner::Bzip2(ref mut rdr) => rdr.read(buf),
+            #[cfg(feature = "xz")]
+            Inner::Xz(ref mut rdr) => rdr.read(buf),
+        }
+    }
+}
+
+impl<'b, R: io::Read> LineBufferReader<'b, DecompressionReader<R>> {
+    /// Create a new buffered reader that decompresses `rdr` if it starts with
+    /// the magic bytes of an enabled compression format.
+    ///
+    /// The format is sniffed in the first fill, and plain text is read
+    /// unchanged.
+    pub fn with_decompression(
+        rdr: R,
+        line_buffer: &'b mut LineBuffer,
+    ) -> LineBufferReader<'b, DecompressionReader<R>> {
+        LineBufferReader::new(DecompressionReader::new(rdr), line_buffer)
+    }
+}
+
+impl<R: io::Read> std::fmt::Debug for DecompressionReader<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DecompressionReader")
+            .field("format", &self.format)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_buffer::LineBufferBuilder;
+    use bstr::ByteVec;
+    use std::io::Read;
+
+    const LINES: &str = "homer\nlisa\nmaggie\nbart\n";
+
+    fn read_lines(compressed: &[u8]) -> (String, Option<CompressionFormat>) {
+        let mut linebuf = LineBufferBuilder::new().capacity(4).build();
+        let mut rdr = LineBufferReader::with_decompression(compressed, &mut linebuf);
+        let mut got = vec![];
+        while rdr.fill().unwrap() {
+            got.push_str(rdr.buffer());
+            rdr.consume_all();
+        }
+        (got.into_string().unwrap(), rdr.get_ref().format())
+    }
+
+    #[test]
+    fn plain() {
+        assert_eq!(read_lines(LINES.as_bytes()), (LINES.to_string(), None));
+        assert_eq!(read_lines(b"a"), ("a".to_string(), None));
+        assert_eq!(read_lines(b""), ("".to_string(), None));
+    }
+
+    #[test]
+    fn failed_state_errors() {
+        let mut rdr = DecompressionReader::new(&b""[..]);
+        rdr.inner = Inner::Empty;
+        assert!(rdr.read(&mut [0; 4]).is_err());
+
+        rdr.inner = Inner::Failed(io::ErrorKind::InvalidData, "bad frame".to_string());
+        for _ in 0..2 {
+            let err = rdr.read(&mut [0; 4]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(err.to_string(), "bad frame");
+        }
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn gzip() {
+        use std::io::Write;
+
+        // Two members, to check that multi-member files are read in full.
+        let mut compressed = vec![];
+        for half in [&LINES[..11], &LINES[11..]].iter() {
+            let mut enc = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+            enc.write_all(half.as_bytes()).unwrap();
+            compressed.extend(enc.finish().unwrap());
+        }
+        let expected = (LINES.to_string(), Some(CompressionFormat::Gzip));
+        assert_eq!(read_lines(&compressed), expected);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd() {
+        let compressed = zstd::stream::encode_all(LINES.as_bytes(), 0).unwrap();
+        let expected = (LINES.to_string(), Some(CompressionFormat::Zstd));
+        assert_eq!(read_lines(&compressed), expected);
+    }
+
+    #[cfg(feature = "bzip2")]
+    #[test]
+    fn bzip2() {
+        use std::io::Write;
+
+        let mut enc = bzip2::write::BzEncoder::new(vec![], bzip2::Compression::default());
+        enc.write_all(LINES.as_bytes()).unwrap();
+        let compressed = enc.finish().unwrap();
+        let expected = (LINES.to_string(), Some(CompressionFormat::Bzip2));
+        assert_eq!(read_lines(&compressed), expected);
+    }
+
+    #[cfg(feature = "xz")]
+    #[test]
+    fn xz() {
+        use std::io::Write;
+
+        let mut enc = xz2::write::XzEncoder::new(vec![], 6);
+        enc.write_all(LINES.as_bytes()).unwrap();
+        let compressed = enc.finish().unwrap();
+        let expected = (LINES.to_string(), Some(CompressionFormat::Xz));
+        assert_eq!(read_lines(&compressed), expected);
+    }
+}
@@ -0,0 +1,145 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+/// An error that can occur while reading lines.
+///
+/// Every error records where in the input it happened. An `Error` converts
+/// into an `io::Error` with the same kind as the underlying I/O error,
+/// `io::ErrorKind::Other` for allocation limits or
+/// `io::ErrorKind::InvalidData` for checkpoint mismatches, and the original
+/// `Error` can be recovered from it with `io::Error::get_ref` and
+/// `downcast_ref`.
+///
+/// More variants may be added in the future, so matches on an `Error` need a
+/// wildcard arm.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// A line didn't fit in the buffer without growing it past the limit set
+    /// with `BufferAllocation::Error`.
+    AllocationLimit {
+        /// The limit that was exceeded, in bytes. For a line buffer, this is
+        /// the capacity of the buffer plus the configured limit. For a
+        /// `LineCodec`, this is the maximum length of a line.
+        limit: usize,
+        /// The absolute byte offset of the start of the line that didn't fit.
+        absolute_offset: u64,
+    },
+    /// The underlying reader returned an error.
+    Io {
+        /// The error returned by the underlying reader.
+        err: io::Error,
+        /// The absolute byte offset of the first byte that couldn't be read.
+        absolute_offset: u64,
+        /// The line number, starting at `1`, of the line that was being read,
+        /// if line numbers are being counted.
+        line_number: Option<u64>,
+    },
+    /// The input doesn't match a checkpoint that reading was resumed from,
+    /// because the bytes before the checkpoint changed, the input is shorter
+    /// than the checkpoint, or the line terminator differs.
+    CheckpointMismatch {
+        /// The absolute byte offset of the checkpoint.
+        absolute_offset: u64,
+    },
+}
+
+impl Error {
+    /// Return the absolute byte offset at which this error occurred.
+    pub fn absolute_offset(&self) -> u64 {
+        match *self {
+            Error::AllocationLimit {
+                absolute_offset, ..
+            }
+            | Error::Io {
+                absolute_offset, ..
+            }
+            | Error::CheckpointMismatch { absolute_offset } => absolute_offset,
+        }
+    }
+
+    /// Return the kind of the `io::Error` this error converts into.
+    pub fn kind(&self) -> io::ErrorKind {
+        match *self {
+            Error::AllocationLimit { .. } => io::ErrorKind::Other,
+            Error::Io { ref err, .. } => err.kind(),
+            Error::CheckpointMismatch { .. } => io::ErrorKind::InvalidData,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Error::AllocationLimit {
+                limit,
+                absolute_offset,
+            } => write!(
+                f,
+                "configured allocation limit ({}) exceeded by line at byte offset {}",
+                limit, absolute_offset
+            ),
+            Error::Io {
+                ref err,
+                absolute_offset,
+                line_number,
+            } => {
+                write!(f, "I/O error at byte offset {}", absolute_offset)?;
+                if let Some(line_number) = line_number {
+                    write!(f, " (line {})", line_number)?;
+                }
+                write!(f, ": {}", err)
+            }
+            Error::CheckpointMismatch { absolute_offset } => write!(
+                f,
+                "input does not match checkpoint at byte offset {}",
+                absolute_offset
+            ),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::AllocationLimit { .. } | Error::CheckpointMismatch { .. } => None,
+            Error::Io { ref err, .. } => Some(err),
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        io::Error::new(err.kind(), err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn into_io_error() {
+        let err = Error::Io {
+            err: io::Error::new(io::ErrorKind::UnexpectedEof, "eof"),
+            absolute_offset: 12,
+            line_number: Some(3),
+        };
+        assert_eq!(err.to_string(), "I/O error at byte offset 12 (line 3): eof");
+        let err = io::Error::from(err);
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        let inner = err.get_ref().unwrap().downcast_ref::<Error>().unwrap();
+        assert_eq!(inner.absolute_offset(), 12);
+
+        let err = io::Error::from(Error::AllocationLimit {
+            limit: 10,
+            absolute_offset: 5,
+        });
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+        match err.get_ref().unwrap().downcast_ref::<Error>() {
+            Some(&Error::AllocationLimit { limit: 10, .. }) => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+}
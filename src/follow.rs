@@ -0,0 +1,511 @@
+/*!
+Follow a growing file, like `tail -F`.
+
+A `FollowReader` reads a file and, instead of reporting EOF, waits for more
+data to be appended. Waiting uses inotify on the file's directory, and falls
+back to polling the file if inotify isn't available. When used with a
+`LineBufferReader`, `fill` therefore blocks until a whole line is available,
+and a final line without a line terminator is held back until its terminator
+has been written.
+
+The file is also checked for truncation and rotation whenever its end is
+reached. If it got shorter, reading starts over at its start. If its path now
+refers to a different file, for example after the log was renamed and a new
+one was created, reading continues with the new file. Either way, a
+`FollowEvent` is queued for the caller. A partial line left at the end of the
+old contents is handed out as is, before any data from the new contents.
+
+This is only available on Linux, with the `follow` feature enabled.
+
+```no_run
+use ripline::{follow::FollowReaderBuilder, line_buffer::LineBufferBuilder};
+
+# fn example() -> std::io::Result<()> {
+let mut line_buffer = LineBufferBuilder::new().build();
+let mut lb_reader = FollowReaderBuilder::new()
+    .start_at_end(true)
+    .build("app.log", &mut line_buffer)?;
+while lb_reader.fill()? {
+    while let Some(event) = lb_reader.next_event() {
+        eprintln!("{:?}", event);
+    }
+    print!("{}", String::from_utf8_lossy(lb_reader.buffer()));
+    lb_reader.consume_all();
+}
+# Ok(())
+# }
+```
+*/
+
+use std::collections::VecDeque;
+use std::ffi::CString;
+use std::fs::{self, File};
+use std::io::{self, Seek, SeekFrom};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::line_buffer::{LineBuffer, LineBufferReader};
+
+/// The default time to wait between checks of the file, if nothing wakes the
+/// reader earlier.
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A change to the followed file that was detected while reading.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FollowEvent {
+    /// The file got shorter, and reading started over at its start.
+    Truncated {
+        /// The absolute byte offset at which the data read after the
+        /// truncation starts.
+        absolute_offset: u64,
+    },
+    /// The path now refers to a different file, which reading continues
+    /// with.
+    Rotated {
+        /// The absolute byte offset at which the data read from the new file
+        /// starts.
+        absolute_offset: u64,
+    },
+}
+
+/// A builder for following files.
+#[derive(Clone, Debug)]
+pub struct FollowReaderBuilder {
+    poll_interval: Duration,
+    idle_timeout: Option<Duration>,
+    start_at_end: bool,
+}
+
+impl Default for FollowReaderBuilder {
+    fn default() -> FollowReaderBuilder {
+        FollowReaderBuilder::new()
+    }
+}
+
+impl FollowReaderBuilder {
+    /// Create a new builder for following a file.
+    pub fn new() -> FollowReaderBuilder {
+        FollowReaderBuilder {
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            idle_timeout: None,
+            start_at_end: false,
+        }
+    }
+
+    /// Open the file at `path` and return a buffered reader that follows it,
+    /// using the given `line_buffer` as an intermediate buffer.
+    ///
+    /// If `start_at_end` is enabled, then absolute byte offsets reported by
+    /// the returned reader are offsets into the file, and line numbers start
+    /// at `1` at its end. After a truncation or rotation, absolute byte
+    /// offsets keep counting the bytes read, across all files.
+    pub fn build<'b, P: AsRef<Path>>(
+        &self,
+        path: P,
+        line_buffer: &'b mut LineBuffer,
+    ) -> io::Result<LineBufferReader<'b, FollowReader>> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = File::open(&path)?;
+        let id = file_id(&file.metadata()?);
+        let pos = if self.start_at_end {
+            file.seek(SeekFrom::End(0))?
+        } else {
+            0
+        };
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let rdr = FollowReader {
+            inotify: Inotify::watch(dir),
+            path,
+            file,
+            id,
+            file_pos: pos,
+            absolute_offset: pos,
+            line_term: line_buffer.line_terminator(),
+            partial: false,
+            poll_interval: self.poll_interval,
+            idle_timeout: self.idle_timeout,
+            events: VecDeque::new(),
+        };
+        let mut lb_reader = LineBufferReader::new(rdr, line_buffer);
+        lb_reader.reset(pos);
+        Ok(lb_reader)
+    }
+
+    /// Set the longest time to wait before checking the file again.
+    ///
+    /// With inotify, the reader usually wakes up as soon as the file changes,
+    /// so this only matters if inotify is unavailable or misses a change,
+    /// for example on network file systems. By default, this is set to one
+    /// second.
+    pub fn poll_interval(&mut self, interval: Duration) -> &mut FollowReaderBuilder {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Stop following the file once no new data has arrived for the given
+    /// time.
+    ///
+    /// When that happens, the reader reports EOF, so `fill` hands out a final
+    /// partial line if there is one, and returns `false` otherwise. By
+    /// default, there is no timeout and the file is followed forever.
+    pub fn idle_timeout(&mut self, timeout: Option<Duration>) -> &mut FollowReaderBuilder {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Start reading at the current end of the file, instead of its start.
+    ///
+    /// This is disabled by default.
+    pub fn start_at_end(&mut self, yes: bool) -> &mut FollowReaderBuilder {
+        self.start_at_end = yes;
+        self
+    }
+}
+
+/// A reader that follows a file as it grows, across truncation and rotation.
+///
+/// Reads block until more data is available, and only return `0` to hand out
+/// a partial line left behind by a truncation or rotation, or once the idle
+/// timeout has passed.
+#[derive(Debug)]
+pub struct FollowReader {
+    path: PathBuf,
+    file: File,
+    /// The device and inode number of `file`.
+    id: (u64, u64),
+    /// The position of the next byte read from `file`.
+    file_pos: u64,
+    /// The absolute byte offset of the next byte read, across all files.
+    absolute_offset: u64,
+    line_term: u8,
+    /// Whether the last byte read wasn't a line terminator.
+    partial: bool,
+    inotify: Option<Inotify>,
+    poll_interval: Duration,
+    idle_timeout: Option<Duration>,
+    events: VecDeque<FollowEvent>,
+}
+
+/// What was found when checking the file after reaching its end.
+enum Change {
+    /// Nothing changed, so there's nothing to read yet.
+    None,
+    /// More data has been appended.
+    Grown,
+    /// The file was truncated or rotated, and an event has been queued.
+    Replaced,
+}
+
+impl FollowReader {
+    /// Return the path of the followed file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Return the oldest change to the file that hasn't been returned yet.
+    pub fn next_event(&mut self) -> Option<FollowEvent> {
+        self.events.pop_front()
+    }
+
+    /// Check the file for changes after reaching its end.
+    fn check(&mut self) -> io::Result<Change> {
+        let len = self.file.metadata()?.len();
+        if len < self.file_pos {
+            self.file.seek(SeekFrom::Start(0))?;
+            self.file_pos = 0;
+            self.events.push_back(FollowEvent::Truncated {
+                absolute_offset: self.absolute_offset,
+            });
+            return Ok(Change::Replaced);
+        }
+        if len > self.file_pos {
+            return Ok(Change::Grown);
+        }
+        // The path may not exist while a log is being rotated, in which case
+        // we wait for the new file to show up.
+        let md = match fs::metadata(&self.path) {
+            Ok(md) => md,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(Change::None),
+            Err(err) => return Err(err),
+        };
+        if file_id(&md) == self.id {
+            return Ok(Change::None);
+        }
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(Change::None),
+            Err(err) => return Err(err),
+        };
+        self.id = file_id(&file.metadata()?);
+        self.file = file;
+        self.file_pos = 0;
+        self.events.push_back(FollowEvent::Rotated {
+            absolute_offset: self.absolute_offset,
+        });
+        Ok(Change::Replaced)
+    }
+
+    /// Block until the file may have changed, or `timeout` has passed.
+    fn wait(&self, timeout: Duration) {
+        match self.inotify {
+            Some(ref inotify) => inotify.wait(timeout),
+            None => thread::sleep(timeout),
+        }
+    }
+}
+
+impl io::Read for FollowReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let start = Instant::now();
+        loop {
+            let n = match self.file.read(buf) {
+                Ok(n) => n,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            if n > 0 {
+                self.file_pos += n as u64;
+                self.absolute_offset += n as u64;
+                self.partial = buf[n - 1] != self.line_term;
+                return Ok(n);
+            }
+            match self.check()? {
+                Change::Grown => continue,
+                Change::Replaced => {
+                    // Report EOF once, so the line buffer hands out the
+                    // partial line instead of gluing it to the new data.
+                    if self.partial {
+                        self.partial = false;
+                        return Ok(0);
+                    }
+                    continue;
+                }
+                Change::None => {}
+            }
+            let mut timeout = self.poll_interval;
+            if let Some(idle_timeout) = self.idle_timeout {
+                match idle_timeout.checked_sub(start.elapsed()) {
+                    Some(left) if !left.is_zero() => timeout = timeout.min(left),
+                    _ => return Ok(0),
+                }
+            }
+            self.wait(timeout);
+        }
+    }
+}
+
+impl<'b> LineBufferReader<'b, FollowReader> {
+    /// Return the oldest change to the followed file that hasn't been
+    /// returned yet.
+    ///
+    /// Events are queued as soon as a change is detected, which happens
+    /// during a fill. The absolute byte offset of an event tells where the
+    /// data read after the change starts.
+    pub fn next_event(&mut self) -> Option<FollowEvent> {
+        self.get_mut().next_event()
+    }
+}
+
+/// Return the device and inode number of a file.
+fn file_id(md: &fs::Metadata) -> (u64, u64) {
+    (md.dev(), md.ino())
+}
+
+/// An inotify instance watching a directory for changes to its files.
+#[derive(Debug)]
+struct Inotify {
+    fd: OwnedFd,
+}
+
+impl Inotify {
+    /// Watch `dir`, or return `None` if inotify isn't available.
+    fn watch(dir: &Path) -> Option<Inotify> {
+        let dir = CString::new(dir.as_os_str().as_bytes()).ok()?;
+        // SAFETY: inotify_init1 takes no pointers, and on success returns a
+        // new file descriptor that nothing else owns.
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return None;
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let mask = libc::IN_MODIFY
+            | libc::IN_ATTRIB
+            | libc::IN_CREATE
+            | libc::IN_DELETE
+            | libc::IN_MOVED_FROM
+            | libc::IN_MOVED_TO;
+        // SAFETY: `dir` is a valid NUL terminated string.
+        let wd = unsafe { libc::inotify_add_watch(fd.as_raw_fd(), dir.as_ptr(), mask) };
+        if wd < 0 {
+            return None;
+        }
+        Some(Inotify { fd })
+    }
+
+    /// Block until a change is reported, or `timeout` has passed, and
+    /// discard the pending events.
+    ///
+    /// Events only serve to wake up early, since the file is checked after
+    /// every wake up anyway.
+    fn wait(&self, timeout: Duration) {
+        let mut pollfd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let millis = timeout.as_millis().clamp(1, libc::c_int::MAX as u128) as libc::c_int;
+        // SAFETY: `pollfd` is a single valid pollfd. Errors, like being
+        // interrupted, only cause an early wake up.
+        unsafe { libc::poll(&mut pollfd, 1, millis) };
+        let mut buf = [0u8; 4096];
+        loop {
+            // SAFETY: `buf` is valid for writes of its length. The descriptor
+            // is non-blocking, so this stops once the events are drained.
+            let n = unsafe { libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+            if n <= 0 {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_buffer::LineBufferBuilder;
+    use std::io::Write;
+
+    /// A directory for a test's files, which is removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let dir = std::env::temp_dir().join(format!(
+                "ripline-follow-{}-{}",
+                name,
+                std::process::id()
+            ));
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn append(path: &Path, data: &str) {
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        file.write_all(data.as_bytes()).unwrap();
+    }
+
+    /// Follow `path` until it's idle, while `write` changes it on another
+    /// thread, and return what each fill handed out along with the events.
+    fn follow<F>(path: &Path, start_at_end: bool, write: F) -> (Vec<String>, Vec<FollowEvent>)
+    where
+        F: FnOnce() + Send,
+    {
+        let mut line_buffer = LineBufferBuilder::new().capacity(4).build();
+        let mut rdr = FollowReaderBuilder::new()
+            .poll_interval(Duration::from_millis(10))
+            .idle_timeout(Some(Duration::from_millis(500)))
+            .start_at_end(start_at_end)
+            .build(path, &mut line_buffer)
+            .unwrap();
+        thread::scope(|s| {
+            s.spawn(write);
+            let mut fills = vec![];
+            let mut events = vec![];
+            while rdr.fill().unwrap() {
+                events.extend(std::iter::from_fn(|| rdr.next_event()));
+                fills.push(String::from_utf8(rdr.buffer().to_vec()).unwrap());
+                rdr.consume_all();
+            }
+            (fills, events)
+        })
+    }
+
+    fn pause() {
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    #[test]
+    fn follow_appends() {
+        let dir = TempDir::new("appends");
+        let path = dir.0.join("log");
+        append(&path, "old\n");
+        let (fills, events) = follow(&path, true, || {
+            pause();
+            append(&path, "homer\nli");
+            pause();
+            append(&path, "sa\n");
+            pause();
+            append(&path, "maggie");
+        });
+        assert!(events.is_empty());
+        let text = fills.concat();
+        assert_eq!(text, "homer\nlisa\nmaggie");
+        // Only the final fill at the idle timeout ends with a partial line.
+        assert!(fills[..fills.len() - 1].iter().all(|f| f.ends_with('\n')));
+    }
+
+    #[test]
+    fn follow_truncation() {
+        let dir = TempDir::new("truncation");
+        let path = dir.0.join("log");
+        append(&path, "homer\nlisa\n");
+        let (fills, events) = follow(&path, false, || {
+            pause();
+            append(&path, "mag");
+            pause();
+            File::create(&path).unwrap();
+            pause();
+            append(&path, "bart\n");
+        });
+        assert_eq!(fills.concat(), "homer\nlisa\nmagbart\n");
+        assert!(fills.contains(&"mag".to_string()));
+        assert_eq!(
+            events,
+            vec![FollowEvent::Truncated {
+                absolute_offset: 14
+            }]
+        );
+    }
+
+    #[test]
+    fn follow_rotation() {
+        let dir = TempDir::new("rotation");
+        let path = dir.0.join("log");
+        append(&path, "homer\n");
+        let (fills, events) = follow(&path, false, || {
+            pause();
+            append(&path, "lisa\n");
+            fs::rename(&path, dir.0.join("log.1")).unwrap();
+            pause();
+            append(&path, "maggie\n");
+        });
+        assert_eq!(fills.concat(), "homer\nlisa\nmaggie\n");
+        assert_eq!(
+            events,
+            vec![FollowEvent::Rotated {
+                absolute_offset: 11
+            }]
+        );
+    }
+}
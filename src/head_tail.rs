@@ -0,0 +1,256 @@
+/*!
+Get the first or last lines of an input, like `head -n` and `tail -n`.
+
+All functions return the lines as one contiguous block of bytes, which can be
+iterated over with `LineIter`. Line terminators are considered part of the
+line they terminate, and a final line without a line terminator is still a
+line.
+
+```no_run
+use ripline::{head_tail, lines::LineIter, LineTerminator};
+use std::fs::File;
+
+# fn example() -> std::io::Result<()> {
+let last = head_tail::tail(File::open("app.log")?, LineTerminator::default(), 10)?;
+for line in LineIter::new(LineTerminator::default(), &last) {
+    print!("{}", String::from_utf8_lossy(line));
+}
+# Ok(())
+# }
+```
+*/
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::io::{self, SeekFrom};
+
+use bstr::ByteSlice;
+
+use crate::line_buffer::DEFAULT_BUFFER_CAPACITY;
+use crate::lines::preceding;
+use crate::LineTerminator;
+
+/// Return the first `count` lines of `rdr`.
+///
+/// Exactly the bytes up to and including the line terminator of the last of
+/// them are consumed from `rdr`, so passing `&mut rdr` leaves it positioned
+/// right after the returned lines. Wrap an `io::Read` in an `io::BufReader`,
+/// which may itself read ahead of that position from the underlying reader.
+/// If `rdr` has fewer lines, then all of them are returned.
+pub fn head<R: io::BufRead>(
+    mut rdr: R,
+    line_term: LineTerminator,
+    mut count: usize,
+) -> io::Result<Vec<u8>> {
+    let mut lines = vec![];
+    while count > 0 {
+        let buf = match rdr.fill_buf() {
+            Ok(buf) => buf,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        if buf.is_empty() {
+            break;
+        }
+        let mut used = 0;
+        while let Some(i) = buf[used..].find_byte(line_term.as_byte()) {
+            used += i + 1;
+            count -= 1;
+            if count == 0 {
+                break;
+            }
+        }
+        if count > 0 {
+            used = buf.len();
+        }
+        lines.extend_from_slice(&buf[..used]);
+        rdr.consume(used);
+    }
+    Ok(lines)
+}
+
+/// Return the last `count` lines of the seekable input `rdr`.
+///
+/// The input is scanned backwards from its end, in a window that grows
+/// until it holds `count` lines, so only the end of the input is read. If
+/// `rdr` has fewer lines, then all of them are returned.
+pub fn tail<R: io::Read + io::Seek>(
+    rdr: R,
+    line_term: LineTerminator,
+    count: usize,
+) -> io::Result<Vec<u8>> {
+    tail_with_capacity(rdr, line_term, count, DEFAULT_BUFFER_CAPACITY)
+}
+
+/// Return the last `count` lines of `rdr`, which doesn't need to be
+/// seekable.
+///
+/// This reads all of `rdr`. The last `count` lines are tracked as a ring of
+/// ranges into a buffer, which is only compacted once it is full and grows to
+/// at least twice the size of the lines it keeps. So lines aren't copied one
+/// by one, and on average every byte is copied at most once however small the
+/// reads from `rdr` are. Memory use is bounded by about twice the size of the
+/// last `count` lines plus the buffer capacity.
+pub fn tail_stream<R: io::Read>(
+    rdr: R,
+    line_term: LineTerminator,
+    count: usize,
+) -> io::Result<Vec<u8>> {
+    tail_stream_with_capacity(rdr, line_term, count, DEFAULT_BUFFER_CAPACITY)
+}
+
+fn tail_with_capacity<R: io::Read + io::Seek>(
+    mut rdr: R,
+    line_term: LineTerminator,
+    count: usize,
+    capacity: usize,
+) -> io::Result<Vec<u8>> {
+    if count == 0 {
+        return Ok(vec![]);
+    }
+    // `buf` holds the input from `offset` to its end.
+    let mut offset = rdr.seek(SeekFrom::End(0))?;
+    let mut buf = vec![];
+    loop {
+        let start = preceding(&buf, line_term, count - 1);
+        // Unless the window starts at the start of the input, a line that
+        // starts at the start of the window may have begun before it.
+        if start > 0 || offset == 0 {
+            buf.drain(..start);
+            return Ok(buf);
+        }
+        // Double the window each time, so long lines don't get shifted
+        // through it over and over again.
+        let len = cmp::min(cmp::max(capacity, buf.len()) as u64, offset) as usize;
+        offset -= len as u64;
+        let old = buf.len();
+        buf.resize(old + len, 0);
+        buf.copy_within(..old, len);
+        rdr.seek(SeekFrom::Start(offset))?;
+        rdr.read_exact(&mut buf[..len])?;
+    }
+}
+
+fn tail_stream_with_capacity<R: io::Read>(
+    mut rdr: R,
+    line_term: LineTerminator,
+    count: usize,
+    capacity: usize,
+) -> io::Result<Vec<u8>> {
+    if count == 0 {
+        return Ok(vec![]);
+    }
+    let line_term = line_term.as_byte();
+    let mut buf = vec![0; cmp::max(1, capacity)];
+    // The last `count` complete lines, as `(start, len)` ranges into `buf`.
+    let mut ring: VecDeque<(usize, usize)> = VecDeque::new();
+    // The start of the partial line after the last complete line.
+    let mut line_start = 0;
+    // The end of the data in `buf`.
+    let mut end = 0;
+    loop {
+        if end == buf.len() {
+            // Drop everything before the oldest line we keep, and make sure
+            // at least half of the buffer is free for reading.
+            let keep = ring.front().map_or(line_start, |&(start, _)| start);
+            buf.copy_within(keep..end, 0);
+            for range in ring.iter_mut() {
+                range.0 -= keep;
+            }
+            line_start -= keep;
+            end -= keep;
+            if end > buf.len() / 2 {
+                buf.resize(buf.len() * 2, 0);
+            }
+        }
+        let n = match rdr.read(&mut buf[end..]) {
+            Ok(n) => n,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        if n == 0 {
+            break;
+        }
+        let mut pos = end;
+        end += n;
+        while let Some(i) = buf[pos..end].find_byte(line_term) {
+            pos += i + 1;
+            ring.push_back((line_start, pos - line_start));
+            if ring.len() > count {
+                ring.pop_front();
+            }
+            line_start = pos;
+        }
+    }
+    // A final line without a line terminator is still a line.
+    if line_start < end {
+        ring.push_back((line_start, end - line_start));
+        if ring.len() > count {
+            ring.pop_front();
+        }
+    }
+    // The lines in the ring are consecutive, and the last one ends at `end`.
+    Ok(ring
+        .front()
+        .map_or(vec![], |&(start, _)| buf[start..end].to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lines::LineIter;
+    use std::io::Cursor;
+
+    /// Check `head`, `tail` and `tail_stream` against `LineIter`.
+    fn check(text: &str, line_term: LineTerminator) {
+        let lines: Vec<&[u8]> = LineIter::new(line_term, text.as_bytes()).collect();
+        for count in 0..lines.len() + 2 {
+            let first = lines[..cmp::min(count, lines.len())].concat();
+            let last = lines[lines.len().saturating_sub(count)..].concat();
+            for &capacity in [1, 3, 16, 10_000].iter() {
+                let rdr = || Cursor::new(text.as_bytes());
+                let msg = format!("count {}, capacity {}", count, capacity);
+                let buffered = io::BufReader::with_capacity(capacity, rdr());
+                let got = head(buffered, line_term, count).unwrap();
+                assert_eq!(got, first, "{}", msg);
+                let tail = tail_with_capacity(rdr(), line_term, count, capacity).unwrap();
+                assert_eq!(tail, last, "{}", msg);
+                let tail = tail_stream_with_capacity(rdr(), line_term, count, capacity).unwrap();
+                assert_eq!(tail, last, "{}", msg);
+            }
+        }
+    }
+
+    #[test]
+    fn head_tail_basics() {
+        let lf = LineTerminator::default();
+        check("", lf);
+        check("\n", lf);
+        check("\n\n\n", lf);
+        check("homer\nlisa\nmaggie\n", lf);
+        check("homer\nlisa\nmaggie", lf);
+        check("homer\r\nlisa\r\n", LineTerminator::crlf());
+    }
+
+    #[test]
+    fn head_tail_long_lines() {
+        let text: String = (0..30)
+            .map(|i| format!("{}\n", "x".repeat((i * 37) % 101)))
+            .collect();
+        check(&text, LineTerminator::default());
+    }
+
+    #[test]
+    fn head_stops_early() {
+        let text: String = (0..10_000).map(|i| format!("line {}\n", i)).collect();
+        let mut rdr = Cursor::new(text.as_bytes());
+        let got = head(&mut rdr, LineTerminator::default(), 2).unwrap();
+        assert_eq!(got, b"line 0\nline 1\n");
+        assert_eq!(rdr.position(), 14);
+
+        let mut rdr = io::BufReader::with_capacity(4, Cursor::new(text.as_bytes()));
+        head(&mut rdr, LineTerminator::default(), 2).unwrap();
+        let got = head(&mut rdr, LineTerminator::default(), 1).unwrap();
+        assert_eq!(got, b"line 2\n");
+    }
+}
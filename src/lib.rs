@@ -1,7 +1,29 @@
 use std::ops;
 
+#[cfg(feature = "async")]
+pub mod async_reader;
+#[cfg(feature = "bgzf")]
+pub mod bgzf;
+pub mod checkpoint;
+#[cfg(feature = "codec")]
+pub mod codec;
+pub mod count;
+#[cfg(any(feature = "gzip", feature = "zstd", feature = "bzip2", feature = "xz"))]
+pub mod decompress;
+mod error;
+#[cfg(all(feature = "follow", target_os = "linux"))]
+pub mod follow;
+pub mod head_tail;
 pub mod line_buffer;
+pub mod line_reader;
 pub mod lines;
+#[cfg(feature = "parallel")]
+pub mod parallel;
+pub mod pipeline;
+pub mod reverse;
+pub mod split;
+
+pub use crate::error::Error;
 
 /// The type of a match.
 ///
@@ -221,7 +243,7 @@ impl LineTerminator {
     pub fn as_bytes(&self) -> &[u8] {
         match self.0 {
             LineTerminatorImp::Byte(ref array) => array,
-            LineTerminatorImp::CRLF => &[b'\r', b'\n'],
+            LineTerminatorImp::CRLF => b"\r\n",
         }
     }
 
@@ -232,7 +254,7 @@ impl LineTerminator {
     /// last byte is `\n`.
     #[inline]
     pub fn is_suffix(&self, slice: &[u8]) -> bool {
-        slice.last().map_or(false, |&b| b == self.as_byte())
+        slice.last().is_some_and(|&b| b == self.as_byte())
     }
 }
 
@@ -1,8 +1,14 @@
 use std::cmp;
+use std::collections::{vec_deque, VecDeque};
 use std::io;
+use std::iter;
 
 use bstr::ByteSlice;
 
+use crate::lines::{self, LineIter};
+use crate::split::seek_line_start;
+use crate::{Error, LineTerminator};
+
 /// The default buffer capacity that we use for the line buffer.
 pub(crate) const DEFAULT_BUFFER_CAPACITY: usize = 64 * (1 << 10); // 64 KB
 
@@ -14,30 +20,154 @@ pub(crate) const DEFAULT_BUFFER_CAPACITY: usize = 64 * (1 << 10); // 64 KB
 /// enabled) that do not fit in the buffer.
 ///
 /// The default is to eagerly allocate without a limit.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Default)]
 pub enum BufferAllocation {
     /// Attempt to expand the size of the buffer until either at least the next
     /// line fits into memory or until all available memory is exhausted.
     ///
     /// This is the default.
+    #[default]
     Eager,
     /// Limit the amount of additional memory allocated to the given size. If
     /// a line is found that requires more memory than is allowed here, then
-    /// stop reading and return an error.
+    /// stop reading and return an `Error::AllocationLimit`.
     Error(usize),
+    /// Limit the length of a line, not counting its line terminator, to the
+    /// given number of bytes. Longer lines are cut to their first `limit`
+    /// bytes, followed by their line terminator, and the rest of the line is
+    /// dropped without being buffered.
+    ///
+    /// Note that, unlike with `Error`, the limit applies to the length of a
+    /// line rather than to additional memory. A value of `0` is treated as
+    /// `1`.
+    Truncate(usize),
+    /// Limit the length of a line, not counting its line terminator, to the
+    /// given number of bytes. Longer lines, including their line terminator,
+    /// are dropped entirely without being buffered.
+    ///
+    /// Note that, unlike with `Error`, the limit applies to the length of a
+    /// line rather than to additional memory.
+    Skip(usize),
+    /// Hand out lines that are longer than the given number of bytes in
+    /// pieces, instead of buffering them in full. Each piece is reported by
+    /// `LineBufferReader::fragment`. A value of `0` is treated as `1`.
+    ///
+    /// A line is only split when more than the given number of bytes of it
+    /// have been read without finding its line terminator. So a long line
+    /// that is read in full along with other lines, for example because it
+    /// fits in the capacity of the buffer, is still handed out whole.
+    ///
+    /// Like `Truncate` and `Skip`, the limit applies to the length of a line
+    /// rather than to additional memory.
+    Fragment(usize),
+}
+
+/// The part of an oversized line held by a line buffer, when the buffer
+/// allocation strategy is `BufferAllocation::Fragment`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Fragment {
+    /// The first piece of a line. More pieces follow.
+    First,
+    /// A piece in the middle of a line. More pieces follow.
+    Middle,
+    /// The last piece of a line, which ends with its line terminator unless
+    /// the line is the last one in the input.
+    Last,
+}
+
+/// The behavior of a line buffer after it has grown to fit a long line.
+///
+/// Under `BufferAllocation::Eager`, a single giant line can grow the buffer
+/// far beyond its configured capacity. This controls whether that memory is
+/// given back once the line has been consumed.
+///
+/// The default is to never shrink the buffer.
+#[derive(Clone, Copy, Debug, Default)]
+pub enum BufferShrink {
+    /// Keep the buffer at the largest size it has grown to.
+    ///
+    /// This is the default.
+    #[default]
+    Never,
+    /// Shrink the buffer back to its configured capacity once it has grown by
+    /// more than the given number of bytes, as soon as the data that hasn't
+    /// been consumed yet fits in the configured capacity.
+    ///
+    /// A value of `0` shrinks the buffer whenever possible. Larger values
+    /// avoid repeatedly reallocating the buffer when many lines are only a
+    /// little longer than the capacity.
+    Threshold(usize),
+}
+
+/// The amount of data a line buffer must hold before a fill returns.
+///
+/// A fill always returns whole lines. These policies make it return enough of
+/// them for records that span a fixed number of lines, like FASTQ, to never
+/// straddle the end of the buffer. A fill that reaches EOF returns whatever is
+/// left regardless of the policy, which `LineBufferReader::at_eof` reports.
+///
+/// The default is to return as soon as any line is available.
+#[derive(Clone, Copy, Debug, Default)]
+pub enum FillPolicy {
+    /// Return as soon as at least one line is available.
+    ///
+    /// This is the default.
+    #[default]
+    Any,
+    /// Return a non-zero multiple of the given number of lines. Lines that
+    /// don't make up a whole record are kept for the next fill.
+    ///
+    /// A value of `0` behaves like `FillPolicy::Any`.
+    Lines(usize),
+    /// Return at least the given number of bytes of whole lines.
+    Bytes(usize),
+}
+
+/// The behavior of binary detection in the line buffer.
+///
+/// Binary detection is the process of _heuristically_ identifying whether a
+/// given chunk of data is binary or not, and then taking an action based on
+/// the result of that heuristic. The motivation behind detecting binary data
+/// is that binary data often indicates data that is undesirable to read as
+/// lines, e.g., an accidentally compressed or otherwise non-text file.
+///
+/// The default is to not perform any binary detection.
+#[derive(Clone, Copy, Debug, Default)]
+pub enum BinaryDetection {
+    /// No binary detection is performed. Data reported by the line buffer may
+    /// contain arbitrary bytes.
+    ///
+    /// This is the default.
+    #[default]
+    None,
+    /// The given byte is searched in all contents read by the line buffer. If
+    /// it occurs, then the data is considered binary and the line buffer acts
+    /// as if it reached EOF. The line buffer guarantees that this byte will
+    /// never be observable by callers.
+    Quit(u8),
+    /// The given byte is searched in all contents read by the line buffer. If
+    /// it occurs, then it is replaced by the line terminator. The line buffer
+    /// guarantees that this byte will never be observable by callers.
+    Convert(u8),
 }
 
-impl Default for BufferAllocation {
-    fn default() -> BufferAllocation {
-        BufferAllocation::Eager
+impl BinaryDetection {
+    /// Returns true if and only if the detection heuristic demands that
+    /// the line buffer stop reading data once binary data is observed.
+    fn is_quit(&self) -> bool {
+        matches!(*self, BinaryDetection::Quit(_))
     }
 }
 
 /// Create a new error to be used when a configured allocation limit has been
 /// reached.
+#[deprecated(
+    since = "0.1.1",
+    note = "`LineBufferReader::fill` returns `Error::AllocationLimit` instead"
+)]
 pub fn alloc_error(limit: usize) -> io::Error {
     let msg = format!("configured allocation limit ({}) exceeded", limit);
-    io::Error::new(io::ErrorKind::Other, msg)
+    io::Error::other(msg)
 }
 
 /// The configuration of a buffer. This contains options that are fixed once
@@ -50,6 +180,16 @@ struct Config {
     lineterm: u8,
     /// The behavior for handling long lines.
     buffer_alloc: BufferAllocation,
+    /// When set, the presence of the given byte indicates binary content.
+    binary: BinaryDetection,
+    /// The behavior for giving back memory after long lines.
+    buffer_shrink: BufferShrink,
+    /// Whether to count lines as they are consumed.
+    line_number: bool,
+    /// The number of consumed lines to keep when rolling the buffer.
+    before_context: usize,
+    /// The amount of data a fill must return.
+    fill_policy: FillPolicy,
 }
 
 impl Default for Config {
@@ -58,6 +198,11 @@ impl Default for Config {
             capacity: DEFAULT_BUFFER_CAPACITY,
             lineterm: b'\n',
             buffer_alloc: BufferAllocation::default(),
+            binary: BinaryDetection::default(),
+            buffer_shrink: BufferShrink::default(),
+            line_number: false,
+            before_context: 0,
+            fill_policy: FillPolicy::default(),
         }
     }
 }
@@ -85,7 +230,15 @@ impl LineBufferBuilder {
             last_lineterm: 0,
             end: 0,
             absolute_byte_offset: 0,
+            line_number: 1,
             binary_byte_offset: None,
+            gaps: VecDeque::new(),
+            discarding: false,
+            overlong_lines: vec![],
+            fragment: None,
+            in_fragment: false,
+            eof: false,
+            high_water_mark: self.config.capacity,
         }
     }
 
@@ -132,10 +285,78 @@ impl LineBufferBuilder {
     /// a value of `0` is sensible, and in particular, will guarantee that a
     /// line buffer will never allocate additional memory beyond its initial
     /// capacity.
+    ///
+    /// Alternatively, `BufferAllocation::Truncate` and `BufferAllocation::Skip`
+    /// bound the length of a line instead, and cut or drop lines that are
+    /// longer without failing. The absolute byte offsets of the affected lines
+    /// are reported by `LineBufferReader::overlong_lines`. With
+    /// `BufferAllocation::Fragment`, long lines are handed out in pieces.
     pub fn buffer_alloc(&mut self, behavior: BufferAllocation) -> &mut LineBufferBuilder {
         self.config.buffer_alloc = behavior;
         self
     }
+
+    /// Whether to enable binary detection or not. Depending on the setting,
+    /// this can either cause the line buffer to report EOF early or it can
+    /// cause the line buffer to clean the data.
+    ///
+    /// By default, this is disabled. In general, binary detection should be
+    /// viewed as an imperfect heuristic.
+    pub fn binary_detection(&mut self, detection: BinaryDetection) -> &mut LineBufferBuilder {
+        self.config.binary = detection;
+        self
+    }
+
+    /// Set whether to shrink the buffer back to its capacity after it has
+    /// grown to fit a long line.
+    ///
+    /// The buffer is only ever shrunk when it is rolled at the start of a
+    /// fill, or when it is reused for a new reader, and only if the data it
+    /// has to keep fits in its configured capacity.
+    ///
+    /// By default, this is set to `BufferShrink::Never`, which keeps the
+    /// buffer at the largest size it has grown to.
+    pub fn buffer_shrink(&mut self, behavior: BufferShrink) -> &mut LineBufferBuilder {
+        self.config.buffer_shrink = behavior;
+        self
+    }
+
+    /// Whether to count lines as they are consumed.
+    ///
+    /// When enabled, `LineBufferReader::line_number` reports the line number
+    /// of the first line in the buffer, and I/O errors report the line that
+    /// was being read. Counting lines requires looking at every consumed byte
+    /// a second time, so this is disabled by default.
+    pub fn line_number(&mut self, yes: bool) -> &mut LineBufferBuilder {
+        self.config.line_number = yes;
+        self
+    }
+
+    /// Set the number of consumed lines to keep available when the buffer
+    /// rolls, for use with `LineBufferReader::before_context`.
+    ///
+    /// Kept lines take up space in the buffer, which counts towards the
+    /// allocation limit of `BufferAllocation::Error`.
+    ///
+    /// By default, this is set to `0`, which discards consumed data when the
+    /// buffer rolls.
+    pub fn before_context(&mut self, lines: usize) -> &mut LineBufferBuilder {
+        self.config.before_context = lines;
+        self
+    }
+
+    /// Set how much data a fill must return, unless it reaches EOF.
+    ///
+    /// Policies that need more than a single line keep reading and growing
+    /// the buffer until they are satisfied, so large values may exceed the
+    /// allocation limit of `BufferAllocation::Error`. Fill policies are
+    /// ignored with `BufferAllocation::Fragment`.
+    ///
+    /// By default, this is set to `FillPolicy::Any`.
+    pub fn fill_policy(&mut self, policy: FillPolicy) -> &mut LineBufferBuilder {
+        self.config.fill_policy = policy;
+        self
+    }
 }
 
 /// A line buffer reader efficiently reads a line oriented buffer from an
@@ -157,6 +378,19 @@ impl<'b, R: io::Read> LineBufferReader<'b, R> {
         LineBufferReader { rdr, line_buffer }
     }
 
+    /// Return a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.rdr
+    }
+
+    /// Return a mutable reference to the underlying reader.
+    ///
+    /// Reading from the underlying reader directly will cause the contents of
+    /// this buffer to skip the data that was read.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.rdr
+    }
+
     /// The absolute byte offset which corresponds to the starting offsets
     /// of the data returned by `buffer` relative to the beginning of the
     /// underlying reader's contents. As such, this offset does not generally
@@ -173,6 +407,76 @@ impl<'b, R: io::Read> LineBufferReader<'b, R> {
         self.line_buffer.binary_byte_offset()
     }
 
+    /// The line number, starting at `1`, of the first line returned by
+    /// `buffer`, relative to the beginning of the underlying reader's
+    /// contents.
+    ///
+    /// This returns `None` unless line counting was enabled with
+    /// `LineBufferBuilder::line_number`.
+    pub fn line_number(&self) -> Option<u64> {
+        self.line_buffer.line_number()
+    }
+
+    /// Return an iterator over the lines in this buffer, along with their line
+    /// numbers and absolute byte offsets.
+    ///
+    /// Line terminators are considered part of the line they terminate.
+    ///
+    /// This returns `None` unless line counting was enabled with
+    /// `LineBufferBuilder::line_number`.
+    pub fn numbered_lines(&self) -> Option<NumberedLines<'_>> {
+        self.line_buffer.numbered_lines()
+    }
+
+    /// Return up to the last `n` lines that were consumed from this buffer,
+    /// right before the contents of `buffer`.
+    ///
+    /// Consumed lines are only guaranteed to be available up to the number
+    /// set with `LineBufferBuilder::before_context`, and fewer lines are
+    /// returned at the start of the input. Line terminators are considered
+    /// part of the line they terminate.
+    pub fn before_context(&self, n: usize) -> &[u8] {
+        self.line_buffer.before_context(n)
+    }
+
+    /// Return the largest size, in bytes, that the underlying line buffer has
+    /// ever grown to.
+    pub fn high_water_mark(&self) -> usize {
+        self.line_buffer.high_water_mark()
+    }
+
+    /// Return the absolute byte offsets of the lines that were cut or dropped
+    /// so far, in input order.
+    ///
+    /// This is always empty unless the buffer allocation strategy is
+    /// `BufferAllocation::Truncate` or `BufferAllocation::Skip`. A line is
+    /// reported as soon as it is found to be too long, which may be before
+    /// the fill that returns the line completes.
+    pub fn overlong_lines(&self) -> &[u64] {
+        self.line_buffer.overlong_lines()
+    }
+
+    /// Return the number of lines that were cut or dropped so far.
+    ///
+    /// This is a convenience function for `overlong_lines().len()`.
+    pub fn overlong_line_count(&self) -> u64 {
+        self.line_buffer.overlong_lines().len() as u64
+    }
+
+    /// If the contents of this buffer are a piece of a line that is too long
+    /// to buffer in full, then return which piece it is.
+    ///
+    /// This is always `None` unless the buffer allocation strategy is
+    /// `BufferAllocation::Fragment`. Otherwise, it is `None` when the buffer
+    /// holds whole lines, and `Some` when the buffer holds a single piece of
+    /// an oversized line. Every oversized line is handed out as a `First`
+    /// piece, any number of `Middle` pieces and a `Last` piece, and none of
+    /// them are empty. Pieces should be consumed in full, since anything left
+    /// over is handed out again as part of the next piece.
+    pub fn fragment(&self) -> Option<Fragment> {
+        self.line_buffer.fragment()
+    }
+
     /// Fill the contents of this buffer by discarding the part of the buffer
     /// that has been consumed. The free space created by discarding the
     /// consumed part of the buffer is then filled with new data from the
@@ -183,18 +487,91 @@ impl<'b, R: io::Read> LineBufferReader<'b, R> {
     /// `Quit`, then the presence of binary data will cause this buffer to
     /// behave as if it had seen EOF at the first occurrence of binary data.)
     ///
-    /// This forwards any errors returned by the underlying reader, and will
-    /// also return an error if the buffer must be expanded past its allocation
-    /// limit, as governed by the buffer allocation strategy.
-    pub fn fill(&mut self) -> Result<bool, io::Error> {
+    /// This forwards any errors returned by the underlying reader as
+    /// `Error::Io`, and will also return an `Error::AllocationLimit` if the
+    /// buffer must be expanded past its allocation limit, as governed by the
+    /// buffer allocation strategy. Either converts into an `io::Error`.
+    pub fn fill(&mut self) -> Result<bool, Error> {
         self.line_buffer.fill(&mut self.rdr)
     }
 
+    /// Fill the buffer until it holds at least `lines` lines beyond the
+    /// current position, without consuming anything.
+    ///
+    /// Unlike `fill`, which may return after reading a single line, this
+    /// keeps reading, growing the buffer as needed, until `buffer` contains
+    /// `lines` complete lines or EOF is reached. At EOF, a final line without
+    /// a line terminator counts as a line. Lines that were already buffered
+    /// count towards `lines`, so calling this repeatedly doesn't read more.
+    ///
+    /// This returns `true` if `buffer` holds at least `lines` lines, and
+    /// `false` if EOF was reached first, in which case `buffer` holds the
+    /// rest of the input. With `BufferAllocation::Fragment`, this also
+    /// returns `false` when the buffer holds a fragment of an oversized line,
+    /// or when the next line is too long to be buffered whole. Either has to
+    /// be consumed before reading on.
+    ///
+    /// The configured `FillPolicy` doesn't apply to the lines read by a
+    /// lookahead. Errors are the same as for `fill`. In particular, buffering
+    /// many lines may exceed the allocation limit of
+    /// `BufferAllocation::Error`.
+    pub fn lookahead(&mut self, lines: usize) -> Result<bool, Error> {
+        let lineterm = LineTerminator::byte(self.line_buffer.line_terminator());
+        let fragments = matches!(
+            self.line_buffer.config.buffer_alloc,
+            BufferAllocation::Fragment(_)
+        );
+        let mut buffered = lines::count(self.buffer(), lineterm);
+        while buffered < lines as u64 {
+            if self.fragment().is_some() {
+                return Ok(false);
+            }
+            self.line_buffer.fill_ahead(&mut self.rdr)?;
+            let before = buffered;
+            buffered = lines::count(self.buffer(), lineterm);
+            if self.at_eof() {
+                let partial = self
+                    .buffer()
+                    .last()
+                    .is_some_and(|&b| b != lineterm.as_byte());
+                return Ok(buffered + partial as u64 >= lines as u64);
+            }
+            // Under `Fragment`, a read that adds no whole line means the next
+            // line is oversized, and only fits in the buffer in pieces.
+            if fragments && buffered == before && self.fragment().is_none() {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Return true if the last fill reached EOF, or binary data under
+    /// `BinaryDetection::Quit`.
+    ///
+    /// In that case, `buffer` holds the rest of the input, which may not
+    /// satisfy the configured `FillPolicy`.
+    pub fn at_eof(&self) -> bool {
+        self.line_buffer.at_eof()
+    }
+
     /// Return the contents of this buffer.
     pub fn buffer(&self) -> &[u8] {
         self.line_buffer.buffer()
     }
 
+    /// Return the line terminator of the underlying line buffer.
+    pub(crate) fn line_terminator(&self) -> u8 {
+        self.line_buffer.line_terminator()
+    }
+
+    /// Discard the contents of this buffer, such that the next fill starts
+    /// at the current position of the underlying reader, which corresponds
+    /// to the given absolute byte offset.
+    pub(crate) fn reset(&mut self, absolute_byte_offset: u64) {
+        self.line_buffer.clear();
+        self.line_buffer.absolute_byte_offset = absolute_byte_offset;
+    }
+
     /// Return the buffer as a BStr, used for convenient equality checking
     pub fn bstr(&self) -> &::bstr::BStr {
         self.buffer().as_bstr()
@@ -215,6 +592,79 @@ impl<'b, R: io::Read> LineBufferReader<'b, R> {
     }
 }
 
+impl<'b, R: io::Read + io::Seek> LineBufferReader<'b, R> {
+    /// Discard the contents of this buffer and seek the underlying reader to
+    /// the given absolute byte offset, such that the next fill starts there.
+    ///
+    /// Absolute byte offsets reported afterwards count from the start of the
+    /// underlying reader, which is only meaningful if the reader was read
+    /// from its start, or was set up with `with_range`. All other state, like
+    /// detected binary data and the offsets of overlong lines, is reset too.
+    /// Line numbers start over at `1`, see `set_line_number` to restore
+    /// them.
+    pub fn seek_to(&mut self, absolute_offset: u64) -> io::Result<()> {
+        let pos = self.rdr.seek(io::SeekFrom::Start(absolute_offset))?;
+        self.reset(pos);
+        Ok(())
+    }
+
+    /// Like `seek_to`, but snap forward to the start of the first line that
+    /// starts at or after `absolute_offset`, and return its offset.
+    ///
+    /// If no line starts at or after `absolute_offset`, then the next fill
+    /// reaches EOF. See `split::seek_line_start` for details.
+    pub fn seek_to_line_start(&mut self, absolute_offset: u64) -> io::Result<u64> {
+        let line_term = self.line_buffer.line_terminator();
+        let pos = seek_line_start(&mut self.rdr, absolute_offset, line_term)?;
+        self.reset(pos);
+        Ok(pos)
+    }
+
+    /// Set the line number of the first line read after a seek, when lines
+    /// are being counted.
+    ///
+    /// This is useful after seeking to a line whose number is known, for
+    /// example from an index built on an earlier pass. This must be called
+    /// before the next fill, and has no effect if line numbers are disabled.
+    pub fn set_line_number(&mut self, line_number: u64) {
+        self.line_buffer.line_number = line_number;
+    }
+}
+
+/// Reads the contents of the buffer, refilling it once it has been consumed.
+///
+/// This makes a line buffer reader usable wherever an `io::Read` is expected,
+/// without buffering the data a second time.
+impl<'b, R: io::Read> io::Read for LineBufferReader<'b, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = io::BufRead::fill_buf(self)?;
+        let n = cmp::min(available.len(), buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        io::BufRead::consume(self, n);
+        Ok(n)
+    }
+}
+
+/// Hands out the contents of the buffer, which is made up of whole lines
+/// except at EOF or for pieces of oversized lines under
+/// `BufferAllocation::Fragment`.
+///
+/// `fill_buf` only refills the buffer once it has been consumed entirely, so
+/// the configured `FillPolicy` and line buffer options apply as they do for
+/// `fill`. Errors from `fill` are converted into an `io::Error`.
+impl<'b, R: io::Read> io::BufRead for LineBufferReader<'b, R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.buffer().is_empty() {
+            self.fill()?;
+        }
+        Ok(self.buffer())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.line_buffer.consume(amt);
+    }
+}
+
 /// A line buffer manages a (typically fixed) buffer for holding lines.
 ///
 /// Callers should create line buffers sparingly and reuse them when possible.
@@ -247,19 +697,56 @@ pub struct LineBuffer {
     /// after the last byte read from the underlying reader. That is, it
     /// becomes the total count of bytes that have been read.
     absolute_byte_offset: u64,
+    /// The line number corresponding to `pos`, if lines are being counted.
+    /// This starts at `1` and is incremented for every line terminator that
+    /// is consumed.
+    line_number: u64,
     /// If binary data was found, this records the absolute byte offset at
     /// which it was first detected.
     binary_byte_offset: Option<u64>,
+    /// Input that was dropped because it belonged to overlong lines, in
+    /// ascending order of position. These are folded into
+    /// `absolute_byte_offset` and `line_number` as the buffer is consumed.
+    gaps: VecDeque<Gap>,
+    /// Whether the rest of an overlong line is being dropped as it is read.
+    discarding: bool,
+    /// The absolute byte offsets of the lines that were cut or dropped.
+    overlong_lines: Vec<u64>,
+    /// The piece of an oversized line handed out by the last fill, if any.
+    fragment: Option<Fragment>,
+    /// Whether the rest of an oversized line is still to be handed out.
+    in_fragment: bool,
+    /// Whether the last fill reached EOF.
+    eof: bool,
+    /// The largest size `buf` has ever grown to. Unlike the other state, this
+    /// is kept when the buffer is cleared.
+    high_water_mark: usize,
 }
 
 impl LineBuffer {
     /// Reset this buffer, such that it can be used with a new reader.
-    fn clear(&mut self) {
+    pub(crate) fn clear(&mut self) {
         self.pos = 0;
         self.last_lineterm = 0;
         self.end = 0;
         self.absolute_byte_offset = 0;
+        self.line_number = 1;
         self.binary_byte_offset = None;
+        self.gaps.clear();
+        self.discarding = false;
+        self.overlong_lines.clear();
+        self.fragment = None;
+        self.in_fragment = false;
+        self.eof = false;
+        self.shrink(0);
+    }
+
+    /// Return the largest size, in bytes, that this buffer has ever grown to.
+    ///
+    /// This includes the configured capacity, and is not reset when the
+    /// buffer is used with a new reader.
+    pub fn high_water_mark(&self) -> usize {
+        self.high_water_mark
     }
 
     /// The absolute byte offset which corresponds to the starting offsets
@@ -269,40 +756,151 @@ impl LineBuffer {
     /// particularly in error messages.
     ///
     /// This is reset to `0` when `clear` is called.
-    fn absolute_byte_offset(&self) -> u64 {
+    pub(crate) fn absolute_byte_offset(&self) -> u64 {
         self.absolute_byte_offset
     }
 
     /// If binary data was detected, then this returns the absolute byte offset
     /// at which binary data was initially found.
-    fn binary_byte_offset(&self) -> Option<u64> {
+    pub(crate) fn binary_byte_offset(&self) -> Option<u64> {
         self.binary_byte_offset
     }
 
+    /// Return the absolute byte offsets of the lines that were cut or dropped
+    /// because of the buffer allocation strategy.
+    pub(crate) fn overlong_lines(&self) -> &[u64] {
+        &self.overlong_lines
+    }
+
+    /// Return the piece of an oversized line handed out by the last fill, if
+    /// any.
+    pub(crate) fn fragment(&self) -> Option<Fragment> {
+        self.fragment
+    }
+
+    /// Return true if the last fill reached EOF.
+    pub(crate) fn at_eof(&self) -> bool {
+        self.eof
+    }
+
+    /// Return the line number of the first line in the buffer, if lines are
+    /// being counted.
+    pub(crate) fn line_number(&self) -> Option<u64> {
+        if self.config.line_number {
+            Some(self.line_number)
+        } else {
+            None
+        }
+    }
+
+    /// Return an iterator over the lines in this buffer, along with their line
+    /// numbers and absolute byte offsets.
+    ///
+    /// This returns `None` if lines aren't being counted.
+    pub(crate) fn numbered_lines(&self) -> Option<NumberedLines<'_>> {
+        if !self.config.line_number {
+            return None;
+        }
+        Some(NumberedLines {
+            lines: LineIter::new(LineTerminator::byte(self.config.lineterm), self.buffer()),
+            gaps: self.gaps.iter().peekable(),
+            buf_pos: self.pos,
+            absolute_offset: self.absolute_byte_offset,
+            line_number: self.line_number,
+            lineterm: self.config.lineterm,
+        })
+    }
+
+    /// Return the absolute byte offset of the given position in `buf`, which
+    /// must not be before `pos`.
+    fn absolute_offset_of(&self, buf_pos: usize) -> u64 {
+        let dropped: u64 = self
+            .gaps
+            .iter()
+            .take_while(|gap| gap.at <= buf_pos)
+            .map(|gap| gap.len)
+            .sum();
+        self.absolute_byte_offset + (buf_pos - self.pos) as u64 + dropped
+    }
+
+    /// Return the line number of the line containing the given position in
+    /// `buf`, which must not be before `pos`, if lines are being counted.
+    fn line_number_of(&self, buf_pos: usize) -> Option<u64> {
+        if !self.config.line_number {
+            return None;
+        }
+        let dropped: u64 = self
+            .gaps
+            .iter()
+            .take_while(|gap| gap.at <= buf_pos)
+            .map(|gap| gap.lines)
+            .sum();
+        let lineterm = self.config.lineterm;
+        let lines = bytecount::count(&self.buf[self.pos..buf_pos], lineterm) as u64;
+        Some(self.line_number + lines + dropped)
+    }
+
+    /// Wrap an error returned by the underlying reader, which happened while
+    /// reading into `free_buffer`.
+    pub(crate) fn io_error(&self, err: io::Error) -> Error {
+        Error::Io {
+            err,
+            absolute_offset: self.absolute_offset_of(self.end),
+            line_number: self.line_number_of(self.end),
+        }
+    }
+
+    /// Return the line terminator of this buffer.
+    pub(crate) fn line_terminator(&self) -> u8 {
+        self.config.lineterm
+    }
+
     /// Return the contents of this buffer.
-    fn buffer(&self) -> &[u8] {
+    pub(crate) fn buffer(&self) -> &[u8] {
         &self.buf[self.pos..self.last_lineterm]
     }
 
+    /// Return up to the last `n` consumed lines that are still in the buffer.
+    pub(crate) fn before_context(&self, n: usize) -> &[u8] {
+        if n == 0 {
+            return &[];
+        }
+        let consumed = &self.buf[..self.pos];
+        let lineterm = LineTerminator::byte(self.config.lineterm);
+        &consumed[lines::preceding(consumed, lineterm, n - 1)..]
+    }
+
     /// Return the contents of the free space beyond the end of the buffer as
     /// a mutable slice.
-    fn free_buffer(&mut self) -> &mut [u8] {
+    pub(crate) fn free_buffer(&mut self) -> &mut [u8] {
         &mut self.buf[self.end..]
     }
 
     /// Consume the number of bytes provided. This must be less than or equal
     /// to the number of bytes returned by `buffer`.
-    fn consume(&mut self, amt: usize) {
+    pub(crate) fn consume(&mut self, amt: usize) {
         assert!(amt <= self.buffer().len());
+        if self.config.line_number {
+            let consumed = &self.buf[self.pos..self.pos + amt];
+            self.line_number += bytecount::count(consumed, self.config.lineterm) as u64;
+        }
         self.pos += amt;
         self.absolute_byte_offset += amt as u64;
+        while let Some(gap) = self.gaps.front() {
+            if gap.at > self.pos {
+                break;
+            }
+            self.absolute_byte_offset += gap.len;
+            self.line_number += gap.lines;
+            self.gaps.pop_front();
+        }
     }
 
     /// Consumes the remainder of the buffer. Subsequent calls to `buffer` are
     /// guaranteed to return an empty slice until the buffer is refilled.
     ///
     /// This is a convenience function for `consume(buffer.len())`.
-    fn consume_all(&mut self) {
+    pub(crate) fn consume_all(&mut self) {
         let amt = self.buffer().len();
         self.consume(amt);
     }
@@ -322,63 +920,407 @@ impl LineBuffer {
     /// This forwards any errors returned by `rdr`, and will also return an
     /// error if the buffer must be expanded past its allocation limit, as
     /// governed by the buffer allocation strategy.
-    fn fill<R: io::Read>(&mut self, mut rdr: R) -> Result<bool, io::Error> {
+    pub(crate) fn fill<R: io::Read>(&mut self, mut rdr: R) -> Result<bool, Error> {
+        let mut more = self.fill_once(&mut rdr)?;
+        while more && !self.fill_done() {
+            more = self.fill_once(&mut rdr)?;
+        }
+        Ok(more)
+    }
+
+    /// Fill the buffer until it holds at least one more line, regardless of
+    /// the fill policy.
+    fn fill_once<R: io::Read>(&mut self, mut rdr: R) -> Result<bool, Error> {
+        if let Some(more) = self.fill_start() {
+            return Ok(more);
+        }
+        loop {
+            self.ensure_capacity()?;
+            let readlen = rdr
+                .read(self.free_buffer().as_bytes_mut())
+                .map_err(|err| self.io_error(err))?;
+            if let Some(more) = self.fill_advance(readlen) {
+                return Ok(more);
+            }
+        }
+    }
+
+    /// Prepare this buffer for reading more data as part of a fill.
+    ///
+    /// If the fill should finish without reading anything, then this returns
+    /// the value the fill should return. Otherwise, callers should repeatedly
+    /// call `ensure_capacity`, read into `free_buffer` and pass the number of
+    /// bytes read to `fill_advance` until it returns a value.
+    pub(crate) fn fill_start(&mut self) -> Option<bool> {
+        if let Some(more) = self.read_start() {
+            return Some(more);
+        }
+
+        // The last piece of an oversized line may have been followed by
+        // whole lines that have been read already, so hand them out first.
+        if let BufferAllocation::Fragment(_) = self.config.buffer_alloc {
+            if !self.in_fragment {
+                let lineterm = self.config.lineterm;
+                if let Some(i) = self.buf[self.pos..self.end].rfind_byte(lineterm) {
+                    self.last_lineterm = self.pos + i + 1;
+                    return Some(true);
+                }
+            }
+        }
+        None
+    }
+
+    /// Like `fill_start`, but never finish without reading, even if whole
+    /// lines that haven't been handed out yet are buffered.
+    fn read_start(&mut self) -> Option<bool> {
+        // If the binary detection heuristic tells us to quit once binary data
+        // has been observed, then we no longer read new data and reach EOF
+        // once the current buffer has been consumed.
+        if self.config.binary.is_quit() && self.binary_byte_offset.is_some() {
+            self.eof = true;
+            return Some(!self.buffer().is_empty());
+        }
+
+        self.eof = false;
+        self.fragment = None;
         self.roll();
-        assert_eq!(self.pos, 0);
+        None
+    }
+
+    /// Read more data without consuming anything, for a lookahead.
+    ///
+    /// Unlike `fill`, this always reads, and ignores the fill policy.
+    fn fill_ahead<R: io::Read>(&mut self, mut rdr: R) -> Result<bool, Error> {
+        if let Some(more) = self.read_start() {
+            return Ok(more);
+        }
         loop {
             self.ensure_capacity()?;
-            let readlen = rdr.read(self.free_buffer().as_bytes_mut())?;
-            if readlen == 0 {
-                // We're only done reading for good once the caller has
-                // consumed everything.
-                self.last_lineterm = self.end;
-                return Ok(!self.buffer().is_empty());
+            let readlen = rdr
+                .read(self.free_buffer().as_bytes_mut())
+                .map_err(|err| self.io_error(err))?;
+            if let Some(more) = self.fill_advance(readlen) {
+                return Ok(more);
+            }
+        }
+    }
+
+    /// Return true if the buffer holds what the fill policy asks for after a
+    /// successful fill, in which case lines that don't make up a whole record
+    /// are held back. Otherwise, the fill should continue with another round
+    /// of `fill_start` and reads, without consuming anything.
+    pub(crate) fn fill_done(&mut self) -> bool {
+        if self.eof {
+            return true;
+        }
+        if let BufferAllocation::Fragment(_) = self.config.buffer_alloc {
+            return true;
+        }
+        match self.config.fill_policy {
+            FillPolicy::Any | FillPolicy::Lines(0) => true,
+            FillPolicy::Bytes(bytes) => self.buffer().len() >= bytes,
+            FillPolicy::Lines(record) => {
+                let lineterm = LineTerminator::byte(self.config.lineterm);
+                let lines = lines::count(self.buffer(), lineterm) as usize;
+                if lines < record {
+                    return false;
+                }
+                let len: usize = LineIter::new(lineterm, self.buffer())
+                    .take(lines - lines % record)
+                    .map(|line| line.len())
+                    .sum();
+                self.last_lineterm = self.pos + len;
+                true
             }
+        }
+    }
 
-            // Get a mutable view into the bytes we've just read. These are
-            // the bytes that we do binary detection on, and also the bytes we
-            // search to find the last line terminator. We need a mutable slice
-            // in the case of binary conversion.
-            let oldend = self.end;
-            self.end += readlen;
-            let newbytes = &mut self.buf[oldend..self.end];
+    /// Account for `readlen` bytes that were just read into `free_buffer`.
+    ///
+    /// If this completes the fill, then the value the fill should return is
+    /// returned. Otherwise, more data needs to be read.
+    pub(crate) fn fill_advance(&mut self, readlen: usize) -> Option<bool> {
+        if readlen == 0 {
+            // We're only done reading for good once the caller has
+            // consumed everything. A line that was being dropped or handed
+            // out in pieces ends here.
+            self.discarding = false;
+            self.end_fragment();
+            self.eof = true;
+            self.last_lineterm = self.end;
+            return Some(!self.buffer().is_empty());
+        }
 
-            // Update our `last_lineterm` positions if we read one.
-            if let Some(i) = newbytes.rfind_byte(self.config.lineterm) {
-                self.last_lineterm = oldend + i + 1;
-                return Ok(true);
+        // Get a mutable view into the bytes we've just read. These are
+        // the bytes that we do binary detection on, and also the bytes we
+        // search to find the last line terminator. We need a mutable slice
+        // in the case of binary conversion.
+        let oldend = self.end;
+        self.end += readlen;
+        let newbytes = &mut self.buf[oldend..self.end];
+
+        // Binary detection.
+        match self.config.binary {
+            BinaryDetection::None => {} // nothing to do
+            BinaryDetection::Quit(byte) => {
+                if let Some(i) = newbytes.find_byte(byte) {
+                    self.end = oldend + i;
+                    self.binary_byte_offset = Some(self.absolute_offset_of(self.end));
+                    self.limit_lines(oldend);
+                    self.end_fragment();
+                    self.eof = true;
+                    self.last_lineterm = self.end;
+                    // If the first byte in our buffer is a binary byte,
+                    // then our buffer is empty and we should report as
+                    // such to the caller.
+                    return Some(self.pos < self.end);
+                }
             }
-            // At this point, if we couldn't find a line terminator, then we
-            // don't have a complete line. Therefore, we try to read more!
+            BinaryDetection::Convert(byte) => {
+                if let Some(i) = replace_bytes(newbytes, byte, self.config.lineterm) {
+                    // Record only the first binary offset.
+                    if self.binary_byte_offset.is_none() {
+                        self.binary_byte_offset = Some(self.absolute_offset_of(oldend + i));
+                    }
+                }
+            }
+        }
+
+        // Cut or drop overlong lines. This may move bytes we've just read
+        // to before `oldend`.
+        let fresh = self.limit_lines(oldend);
+        if let BufferAllocation::Fragment(limit) = self.config.buffer_alloc {
+            return self.fragment_lines(oldend, cmp::max(1, limit));
         }
+
+        // Update our `last_lineterm` positions if we read one.
+        let lineterm = self.config.lineterm;
+        if let Some(i) = self.buf[fresh..self.end].rfind_byte(lineterm) {
+            self.last_lineterm = fresh + i + 1;
+            return Some(true);
+        }
+        // At this point, if we couldn't find a line terminator, then we
+        // don't have a complete line. Therefore, we try to read more!
+        None
     }
 
-    /// Roll the unconsumed parts of the buffer to the front.
+    /// Roll the unconsumed parts of the buffer to the front, along with the
+    /// configured number of consumed lines of before context.
     ///
     /// This operation is idempotent.
     ///
     /// After rolling, `last_lineterm` and `end` point to the same location,
-    /// and `pos` is always set to `0`.
+    /// and `pos` points just past the before context, which is `0` if there
+    /// is none.
     fn roll(&mut self) {
-        if self.pos == self.end {
+        let keep_from = match self.config.before_context {
+            0 => self.pos,
+            n => {
+                let lineterm = LineTerminator::byte(self.config.lineterm);
+                lines::preceding(&self.buf[..self.pos], lineterm, n - 1)
+            }
+        };
+        if keep_from == self.end {
             self.pos = 0;
             self.last_lineterm = 0;
             self.end = 0;
+            self.shrink(0);
             return;
         }
 
-        let roll_len = self.end - self.pos;
-        self.buf.copy_within_str(self.pos..self.end, 0);
-        self.pos = 0;
+        let roll_len = self.end - keep_from;
+        self.buf.copy_within_str(keep_from..self.end, 0);
+        for gap in self.gaps.iter_mut() {
+            gap.at -= keep_from;
+        }
+        self.pos -= keep_from;
         self.last_lineterm = roll_len;
         self.end = roll_len;
+        self.shrink(roll_len);
+    }
+
+    /// Enforce the line length limit of `BufferAllocation::Truncate` and
+    /// `BufferAllocation::Skip` on the bytes in `buf[oldend..end]`, which
+    /// were just read.
+    ///
+    /// Overlong lines are cut or dropped by removing bytes from the buffer.
+    /// This returns the position from which the contents of the buffer
+    /// changed, which is `oldend` unless bytes before it were removed.
+    fn limit_lines(&mut self, oldend: usize) -> usize {
+        let (limit, skip) = match self.config.buffer_alloc {
+            // An empty prefix would leave the dropped bytes at the start of
+            // the line, where they'd be counted before it.
+            BufferAllocation::Truncate(limit) => (cmp::max(limit, 1), false),
+            BufferAllocation::Skip(limit) => (limit, true),
+            BufferAllocation::Eager
+            | BufferAllocation::Error(_)
+            | BufferAllocation::Fragment(_) => return oldend,
+        };
+        let lineterm = self.config.lineterm;
+        let mut fresh = oldend;
+        // The start of the line being checked.
+        let mut line_start = if self.discarding {
+            match self.buf[oldend..self.end].find_byte(lineterm) {
+                None => {
+                    self.remove(oldend, self.end);
+                    return oldend;
+                }
+                Some(t) => {
+                    // When truncating, keep the line terminator.
+                    let t = oldend + t;
+                    self.remove(oldend, if skip { t + 1 } else { t });
+                    self.discarding = false;
+                    if skip {
+                        oldend
+                    } else {
+                        oldend + 1
+                    }
+                }
+            }
+        } else {
+            self.buf[self.pos..oldend]
+                .rfind_byte(lineterm)
+                .map_or(self.pos, |t| self.pos + t + 1)
+        };
+        // The position from which to search for the line's terminator.
+        let mut i = cmp::max(line_start, oldend);
+        loop {
+            let t = self.buf[i..self.end].find_byte(lineterm).map(|t| i + t);
+            let line_end = t.unwrap_or(self.end);
+            if line_end - line_start > limit {
+                let offset = self.absolute_offset_of(line_start);
+                self.overlong_lines.push(offset);
+                let cut = if skip { line_start } else { line_start + limit };
+                fresh = cmp::min(fresh, cut);
+                match t {
+                    Some(t) => self.remove(cut, if skip { t + 1 } else { t }),
+                    None => {
+                        self.remove(cut, self.end);
+                        self.discarding = true;
+                        return fresh;
+                    }
+                }
+                if !skip {
+                    line_start += limit + 1;
+                }
+            } else if t.is_some() {
+                line_start = line_end + 1;
+            } else {
+                return fresh;
+            }
+            i = line_start;
+        }
+    }
+
+    /// Decide what to hand out under `BufferAllocation::Fragment`, after the
+    /// bytes in `buf[oldend..end]` have been read.
+    ///
+    /// Lines up to `limit` bytes long are handed out whole. When the trailing
+    /// partial line grows past `limit`, it is handed out as a piece instead,
+    /// once all whole lines before it have been handed out. The last byte
+    /// read is always held back from `First` and `Middle` pieces, so that a
+    /// `Last` piece is never empty.
+    fn fragment_lines(&mut self, oldend: usize, limit: usize) -> Option<bool> {
+        let lineterm = self.config.lineterm;
+        if self.in_fragment {
+            if let Some(i) = self.buf[oldend..self.end].find_byte(lineterm) {
+                self.last_lineterm = oldend + i + 1;
+                self.end_fragment();
+                return Some(true);
+            }
+            if self.end - self.pos > limit {
+                self.last_lineterm = self.end - 1;
+                self.fragment = Some(Fragment::Middle);
+                return Some(true);
+            }
+            return None;
+        }
+
+        let line_start = self.buf[self.pos..self.end]
+            .rfind_byte(lineterm)
+            .map_or(self.pos, |i| self.pos + i + 1);
+        if self.end - line_start > limit {
+            if line_start > self.pos {
+                self.last_lineterm = line_start;
+            } else {
+                self.last_lineterm = self.end - 1;
+                self.fragment = Some(Fragment::First);
+                self.in_fragment = true;
+            }
+            return Some(true);
+        }
+        if line_start > oldend {
+            self.last_lineterm = line_start;
+            return Some(true);
+        }
+        None
+    }
+
+    /// If an oversized line is being handed out in pieces, then mark the
+    /// contents of the buffer as its last piece.
+    fn end_fragment(&mut self) {
+        if self.in_fragment {
+            self.fragment = Some(Fragment::Last);
+            self.in_fragment = false;
+        }
+    }
+
+    /// Remove `buf[start..end]` from the buffer, and remember that these bytes
+    /// were dropped so that absolute offsets and line numbers stay accurate.
+    fn remove(&mut self, start: usize, end: usize) {
+        if start == end {
+            return;
+        }
+        let len = (end - start) as u64;
+        let lines = if self.config.line_number {
+            bytecount::count(&self.buf[start..end], self.config.lineterm) as u64
+        } else {
+            0
+        };
+        self.buf.copy_within(end..self.end, start);
+        self.end -= end - start;
+        if start == self.pos {
+            self.absolute_byte_offset += len;
+            self.line_number += lines;
+            return;
+        }
+        match self.gaps.back_mut() {
+            Some(gap) if gap.at == start => {
+                gap.len += len;
+                gap.lines += lines;
+            }
+            _ => {
+                debug_assert!(self.gaps.back().map_or(true, |gap| gap.at < start));
+                self.gaps.push_back(Gap {
+                    at: start,
+                    len,
+                    lines,
+                });
+            }
+        }
+    }
+
+    /// Shrink the buffer back to its configured capacity if the shrink policy
+    /// asks for it and the first `keep` bytes, which must be the only data in
+    /// the buffer, leave room to read more.
+    fn shrink(&mut self, keep: usize) {
+        let threshold = match self.config.buffer_shrink {
+            BufferShrink::Never => return,
+            BufferShrink::Threshold(threshold) => threshold,
+        };
+        let capacity = self.config.capacity;
+        if self.buf.len() - capacity <= threshold || keep >= capacity {
+            return;
+        }
+        self.buf.truncate(capacity);
+        self.buf.shrink_to_fit();
     }
 
     /// Ensures that the internal buffer has a non-zero amount of free space
     /// in which to read more data. If there is no free space, then more is
     /// allocated. If the allocation must exceed the configured limit, then
     /// this returns an error.
-    fn ensure_capacity(&mut self) -> Result<(), io::Error> {
+    pub(crate) fn ensure_capacity(&mut self) -> Result<(), Error> {
         if !self.free_buffer().is_empty() {
             return Ok(());
         }
@@ -386,12 +1328,24 @@ impl LineBuffer {
         // is permitted to start at `0`, so we make sure it's at least `1`.
         let len = cmp::max(1, self.buf.len());
         let additional = match self.config.buffer_alloc {
-            BufferAllocation::Eager => len * 2,
+            // Lines are bounded by `Truncate`, `Skip` and `Fragment`, so the
+            // buffer never needs to grow much.
+            BufferAllocation::Eager
+            | BufferAllocation::Truncate(_)
+            | BufferAllocation::Skip(_)
+            | BufferAllocation::Fragment(_) => len * 2,
             BufferAllocation::Error(limit) => {
                 let used = self.buf.len() - self.config.capacity;
                 let n = cmp::min(len * 2, limit - used);
                 if n == 0 {
-                    return Err(alloc_error(self.config.capacity + limit));
+                    let lineterm = self.config.lineterm;
+                    let line_start = self.buf[self.pos..self.end]
+                        .rfind_byte(lineterm)
+                        .map_or(self.pos, |i| self.pos + i + 1);
+                    return Err(Error::AllocationLimit {
+                        limit: self.config.capacity + limit,
+                        absolute_offset: self.absolute_offset_of(line_start),
+                    });
                 }
                 n
             }
@@ -399,23 +1353,121 @@ impl LineBuffer {
         assert!(additional > 0);
         let newlen = self.buf.len() + additional;
         self.buf.resize(newlen, 0);
+        self.high_water_mark = cmp::max(self.high_water_mark, newlen);
         assert!(!self.free_buffer().is_empty());
         Ok(())
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use bstr::{ByteSlice, ByteVec};
+/// Input that was dropped from a line buffer.
+#[derive(Clone, Copy, Debug)]
+struct Gap {
+    /// The position in `buf` right before which input was dropped. This is
+    /// always after `pos`.
+    at: usize,
+    /// The number of bytes dropped.
+    len: u64,
+    /// The number of line terminators dropped, if lines are being counted.
+    lines: u64,
+}
 
-    #[test]
-    fn buffer_basics1() {
-        let bytes = "homer\nlisa\nmaggie";
-        let mut linebuf = LineBufferBuilder::new().build();
-        let mut rdr = LineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+/// An iterator over the lines in a line buffer, along with their line numbers
+/// and absolute byte offsets.
+///
+/// This is created by `LineBufferReader::numbered_lines`.
+#[derive(Debug)]
+pub struct NumberedLines<'b> {
+    lines: LineIter<'b>,
+    gaps: iter::Peekable<vec_deque::Iter<'b, Gap>>,
+    /// The position in `buf` of the next line.
+    buf_pos: usize,
+    absolute_offset: u64,
+    line_number: u64,
+    lineterm: u8,
+}
 
-        assert!(rdr.buffer().is_empty());
+impl<'b> Iterator for NumberedLines<'b> {
+    type Item = (u64, u64, &'b [u8]);
+
+    fn next(&mut self) -> Option<(u64, u64, &'b [u8])> {
+        let line = self.lines.next()?;
+        let buf_pos = self.buf_pos;
+        while let Some(gap) = self.gaps.next_if(|gap| gap.at <= buf_pos) {
+            self.absolute_offset += gap.len;
+            self.line_number += gap.lines;
+        }
+        let item = (self.line_number, self.absolute_offset, line);
+        self.buf_pos += line.len();
+        self.absolute_offset += line.len() as u64;
+        if line.last() == Some(&self.lineterm) {
+            self.line_number += 1;
+        }
+        Some(item)
+    }
+}
+
+/// Replaces `src` with `replacement` in bytes, and return the offset of the
+/// first replacement, if one exists.
+fn replace_bytes(bytes: &mut [u8], src: u8, replacement: u8) -> Option<usize> {
+    if src == replacement {
+        return None;
+    }
+    let mut first_pos = None;
+    let mut pos = 0;
+    while let Some(i) = bytes[pos..].find_byte(src).map(|i| pos + i) {
+        if first_pos.is_none() {
+            first_pos = Some(i);
+        }
+        bytes[i] = replacement;
+        pos = i + 1;
+        while bytes.get(pos) == Some(&src) {
+            bytes[pos] = replacement;
+            pos += 1;
+        }
+    }
+    first_pos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bstr::{ByteSlice, ByteVec};
+
+    const SHERLOCK: &str = "\
+For the Doctor Watsons of this world, as opposed to the Sherlock
+Holmeses, success in the province of detective work must always
+be, to a very large extent, the result of luck. Sherlock Holmes
+can extract a clew from a wisp of straw or a flake of cigar ash;
+but Doctor Watson has to have it taken out for him and dusted,
+and exhibited clearly, with a label attached.\
+";
+
+    fn s(slice: &str) -> String {
+        slice.to_string()
+    }
+
+    fn replace_str(slice: &str, src: u8, replacement: u8) -> (String, Option<usize>) {
+        let mut dst = Vec::from(slice);
+        let result = replace_bytes(&mut dst, src, replacement);
+        (dst.into_string().unwrap(), result)
+    }
+
+    #[test]
+    fn replace() {
+        assert_eq!(replace_str("abc", b'b', b'z'), (s("azc"), Some(1)));
+        assert_eq!(replace_str("abb", b'b', b'z'), (s("azz"), Some(1)));
+        assert_eq!(replace_str("aba", b'a', b'z'), (s("zbz"), Some(0)));
+        assert_eq!(replace_str("bbb", b'b', b'z'), (s("zzz"), Some(0)));
+        assert_eq!(replace_str("bac", b'b', b'z'), (s("zac"), Some(0)));
+    }
+
+    #[test]
+    fn buffer_basics1() {
+        let bytes = "homer\nlisa\nmaggie";
+        let mut linebuf = LineBufferBuilder::new().build();
+        let mut rdr = LineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+
+        assert!(rdr.buffer().is_empty());
 
         assert!(rdr.fill().unwrap());
         assert_eq!(rdr.bstr(), "homer\nlisa\n");
@@ -590,4 +1642,732 @@ mod tests {
         assert!(rdr.fill().is_err());
         assert_eq!(rdr.bstr(), "");
     }
+
+    /// Read all of `bytes` with the given buffer allocation strategy, and
+    /// return the lines read along with their absolute offsets, and the
+    /// offsets of overlong lines.
+    fn read_limited(
+        bytes: &str,
+        capacity: usize,
+        alloc: BufferAllocation,
+    ) -> (Vec<(u64, String)>, Vec<u64>) {
+        let mut linebuf = LineBufferBuilder::new()
+            .capacity(capacity)
+            .buffer_alloc(alloc)
+            .build();
+        let mut rdr = LineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+        let mut lines = vec![];
+        while rdr.fill().unwrap() {
+            let line_end = rdr
+                .buffer()
+                .find_byte(b'\n')
+                .map_or(rdr.buffer().len(), |i| i + 1);
+            let line = rdr.buffer()[..line_end].to_str().unwrap().to_string();
+            lines.push((rdr.absolute_byte_offset(), line));
+            rdr.consume(line_end);
+        }
+        assert_eq!(rdr.absolute_byte_offset(), bytes.len() as u64);
+        assert_eq!(rdr.overlong_line_count(), rdr.overlong_lines().len() as u64);
+        (lines, rdr.overlong_lines().to_vec())
+    }
+
+    #[test]
+    fn buffer_truncate() {
+        let bytes = "homer\nlisa simpson\n\nmaggie simpson\nbart";
+        for &capacity in [1, 4, 64].iter() {
+            let (lines, overlong) = read_limited(bytes, capacity, BufferAllocation::Truncate(6));
+            assert_eq!(
+                lines,
+                vec![
+                    (0, s("homer\n")),
+                    (6, s("lisa s\n")),
+                    (19, s("\n")),
+                    (20, s("maggie\n")),
+                    (35, s("bart")),
+                ],
+                "capacity {}",
+                capacity,
+            );
+            assert_eq!(overlong, vec![6, 20]);
+        }
+
+        let bytes = "c\nab\n\nxyz";
+        for &capacity in [1, 4, 64].iter() {
+            let (lines, overlong) = read_limited(bytes, capacity, BufferAllocation::Truncate(0));
+            assert_eq!(
+                lines,
+                vec![(0, s("c\n")), (2, s("a\n")), (5, s("\n")), (6, s("x"))],
+                "capacity {}",
+                capacity,
+            );
+            assert_eq!(overlong, vec![2, 6]);
+        }
+        let mut linebuf = LineBufferBuilder::new()
+            .buffer_alloc(BufferAllocation::Truncate(0))
+            .line_number(true)
+            .build();
+        let mut rdr = LineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+        assert!(rdr.fill().unwrap());
+        let lines: Vec<(u64, u64, &[u8])> = rdr.numbered_lines().unwrap().collect();
+        assert_eq!(
+            lines,
+            vec![(1, 0, &b"c\n"[..]), (2, 2, &b"a\n"[..]), (3, 5, &b"\n"[..]),]
+        );
+    }
+
+    #[test]
+    fn buffer_truncate_last_line() {
+        let bytes = "homer\nlisa simpson";
+        let (lines, overlong) = read_limited(bytes, 2, BufferAllocation::Truncate(4));
+        assert_eq!(lines, vec![(0, s("home\n")), (6, s("lisa"))]);
+        assert_eq!(overlong, vec![0, 6]);
+    }
+
+    #[test]
+    fn buffer_skip() {
+        let bytes = "homer\nlisa simpson\nbart simpson\n\nmaggie simpson";
+        for &capacity in [1, 4, 64].iter() {
+            let (lines, overlong) = read_limited(bytes, capacity, BufferAllocation::Skip(5));
+            assert_eq!(
+                lines,
+                vec![(0, s("homer\n")), (32, s("\n"))],
+                "capacity {}",
+                capacity,
+            );
+            assert_eq!(overlong, vec![6, 19, 33]);
+        }
+    }
+
+    #[test]
+    fn buffer_skip_binary_quit() {
+        let bytes = "homer\nlisa simpson\x00\nmaggie\n";
+        let mut linebuf = LineBufferBuilder::new()
+            .buffer_alloc(BufferAllocation::Skip(5))
+            .binary_detection(BinaryDetection::Quit(b'\x00'))
+            .build();
+        let mut rdr = LineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+        assert!(rdr.fill().unwrap());
+        assert_eq!(rdr.bstr(), "homer\n");
+        rdr.consume_all();
+        assert!(!rdr.fill().unwrap());
+        assert_eq!(rdr.binary_byte_offset(), Some(18));
+        assert_eq!(rdr.overlong_lines(), &[6]);
+    }
+
+    /// Read all of `bytes` in fragment mode, and return the contents of each
+    /// fill along with the fragment it was reported as.
+    fn read_fragments(
+        bytes: &str,
+        capacity: usize,
+        limit: usize,
+    ) -> Vec<(Option<Fragment>, String)> {
+        let mut linebuf = LineBufferBuilder::new()
+            .capacity(capacity)
+            .buffer_alloc(BufferAllocation::Fragment(limit))
+            .build();
+        let mut rdr = LineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+        let mut fills = vec![];
+        while rdr.fill().unwrap() {
+            assert!(!rdr.buffer().is_empty());
+            fills.push((rdr.fragment(), rdr.buffer().to_str().unwrap().to_string()));
+            rdr.consume_all();
+        }
+        assert!(rdr.high_water_mark() <= cmp::max(capacity, 1) * 2 + limit * 4);
+        let got: String = fills.iter().map(|(_, fill)| fill.as_str()).collect();
+        assert_eq!(got, bytes);
+        fills
+    }
+
+    #[test]
+    fn buffer_fragment() {
+        let long = "x".repeat(50);
+        let bytes = format!("homer\n{}\nlisa\nmaggie\n", long);
+        for &capacity in [1, 4, 16].iter() {
+            let fills = read_fragments(&bytes, capacity, 10);
+            let pieces: Vec<&(Option<Fragment>, String)> =
+                fills.iter().filter(|(frag, _)| frag.is_some()).collect();
+            assert_eq!(pieces[0].0, Some(Fragment::First));
+            for piece in &pieces[1..pieces.len() - 1] {
+                assert_eq!(piece.0, Some(Fragment::Middle));
+            }
+            assert_eq!(pieces[pieces.len() - 1].0, Some(Fragment::Last));
+            let line: String = pieces.iter().map(|(_, piece)| piece.as_str()).collect();
+            assert_eq!(line, format!("{}\n", long), "capacity {}", capacity);
+            for (frag, fill) in &fills {
+                if frag.is_none() {
+                    assert!(fill.ends_with('\n'));
+                    assert!(!fill.contains(&long));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn buffer_fragment_fits() {
+        let bytes = format!("homer\n{}\nlisa\n", "x".repeat(50));
+        let fills = read_fragments(&bytes, 1000, 10);
+        assert_eq!(fills, vec![(None, bytes)]);
+    }
+
+    #[test]
+    fn buffer_fragment_last_line() {
+        let fills = read_fragments("homer\nmaggie simpson", 4, 5);
+        assert_eq!(fills[0], (None, s("homer\n")));
+        assert_eq!(fills[1].0, Some(Fragment::First));
+        assert_eq!(fills[fills.len() - 1].0, Some(Fragment::Last));
+        assert!(fills[fills.len() - 1].1.ends_with('n'));
+    }
+
+    #[test]
+    fn buffer_fragment_limit() {
+        // Lines that are exactly as long as the limit are handed out whole.
+        let fills = read_fragments("homer\nlisa\n", 64, 5);
+        assert_eq!(fills, vec![(None, s("homer\nlisa\n"))]);
+    }
+
+    #[test]
+    fn buffer_allocation_limit_error() {
+        let bytes = "homer\nlisa simpson\n";
+        let mut linebuf = LineBufferBuilder::new()
+            .capacity(4)
+            .buffer_alloc(BufferAllocation::Error(4))
+            .build();
+        let mut rdr = LineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+        assert!(rdr.fill().unwrap());
+        assert_eq!(rdr.bstr(), "homer\n");
+        rdr.consume_all();
+        match rdr.fill() {
+            Err(Error::AllocationLimit {
+                limit: 8,
+                absolute_offset: 6,
+            }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn buffer_io_error() {
+        struct Failing<'a>(&'a [u8]);
+
+        impl io::Read for Failing<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                if self.0.is_empty() {
+                    return Err(io::Error::new(io::ErrorKind::BrokenPipe, "gone"));
+                }
+                let n = cmp::min(buf.len(), self.0.len());
+                buf[..n].copy_from_slice(&self.0[..n]);
+                self.0 = &self.0[n..];
+                Ok(n)
+            }
+        }
+
+        let mut linebuf = LineBufferBuilder::new().line_number(true).build();
+        let mut rdr = LineBufferReader::new(Failing(b"homer\nli"), &mut linebuf);
+        assert!(rdr.fill().unwrap());
+        rdr.consume_all();
+        let err = rdr.fill().unwrap_err();
+        assert_eq!(err.absolute_offset(), 8);
+        match err {
+            Error::Io {
+                line_number: Some(2),
+                ..
+            } => {}
+            ref other => panic!("unexpected error: {:?}", other),
+        }
+        assert_eq!(io::Error::from(err).kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn buffer_line_numbers() {
+        let bytes = "homer\nlisa\nmaggie\nbart";
+        let mut linebuf = LineBufferBuilder::new()
+            .capacity(8)
+            .line_number(true)
+            .build();
+        let mut rdr = LineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+
+        let mut got = vec![];
+        while rdr.fill().unwrap() {
+            let line_number = rdr.line_number().unwrap();
+            let lines: Vec<(u64, u64, String)> = rdr
+                .numbered_lines()
+                .unwrap()
+                .map(|(n, offset, line)| (n, offset, line.to_str().unwrap().to_string()))
+                .collect();
+            assert_eq!(lines[0].0, line_number);
+            got.extend(lines);
+            // Consume the first line only, to count lines across fills.
+            let first_line = rdr
+                .buffer()
+                .find_byte(b'\n')
+                .map_or(rdr.buffer().len(), |i| i + 1);
+            rdr.consume(first_line);
+        }
+        got.dedup();
+        assert_eq!(
+            got,
+            vec![
+                (1, 0, s("homer\n")),
+                (2, 6, s("lisa\n")),
+                (3, 11, s("maggie\n")),
+                (4, 18, s("bart")),
+            ]
+        );
+        assert_eq!(rdr.line_number(), Some(4));
+    }
+
+    #[test]
+    fn buffer_line_numbers_skip() {
+        let bytes = "homer\nlisa simpson\nbart simpson\nmaggie\n";
+        let mut linebuf = LineBufferBuilder::new()
+            .capacity(64)
+            .buffer_alloc(BufferAllocation::Skip(6))
+            .line_number(true)
+            .build();
+        let mut rdr = LineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+        assert!(rdr.fill().unwrap());
+        let lines: Vec<(u64, u64, &[u8])> = rdr.numbered_lines().unwrap().collect();
+        assert_eq!(
+            lines,
+            vec![(1, 0, &b"homer\n"[..]), (4, 32, &b"maggie\n"[..])]
+        );
+    }
+
+    #[test]
+    fn buffer_line_numbers_disabled() {
+        let mut linebuf = LineBufferBuilder::new().build();
+        let mut rdr = LineBufferReader::new("homer\n".as_bytes(), &mut linebuf);
+        assert!(rdr.fill().unwrap());
+        assert_eq!(rdr.line_number(), None);
+        assert!(rdr.numbered_lines().is_none());
+    }
+
+    #[test]
+    fn buffer_before_context() {
+        let bytes = "homer\nlisa\nmaggie\nbart\nmarge\n";
+        for &capacity in [1, 4, 16, 1000].iter() {
+            let mut linebuf = LineBufferBuilder::new()
+                .capacity(capacity)
+                .before_context(2)
+                .build();
+            let mut rdr = LineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+            let mut seen = vec![];
+            while rdr.fill().unwrap() {
+                assert_eq!(rdr.before_context(0), b"");
+                let start = seen.len().saturating_sub(2);
+                let expected: Vec<u8> = seen[start..].concat();
+                assert_eq!(
+                    rdr.before_context(2),
+                    &expected[..],
+                    "capacity {}",
+                    capacity
+                );
+
+                // Consume one line at a time, so context spans fills.
+                let line = rdr.buffer().lines_with_terminator().next().unwrap();
+                let len = line.len();
+                seen.push(line.to_vec());
+                rdr.consume(len);
+            }
+            assert_eq!(seen.concat(), bytes.as_bytes());
+        }
+    }
+
+    #[test]
+    fn buffer_before_context_disabled() {
+        let mut linebuf = LineBufferBuilder::new().capacity(4).build();
+        let mut rdr = LineBufferReader::new("homer\nlisa\n".as_bytes(), &mut linebuf);
+        assert!(rdr.fill().unwrap());
+        rdr.consume(6);
+        assert!(rdr.fill().unwrap());
+        assert_eq!(rdr.buffer(), b"lisa\n");
+        assert_eq!(rdr.before_context(1), b"");
+    }
+
+    #[test]
+    fn buffer_lookahead() {
+        let bytes = "homer\nlisa\nmaggie\nbart";
+        for &capacity in [1, 4, 1000].iter() {
+            let mut linebuf = LineBufferBuilder::new().capacity(capacity).build();
+            let mut rdr = LineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+
+            assert!(rdr.lookahead(0).unwrap());
+            assert!(rdr.lookahead(2).unwrap());
+            assert!(rdr.buffer().starts_with(b"homer\nlisa\n"));
+            rdr.consume(6);
+            assert!(rdr.lookahead(3).unwrap());
+            assert_eq!(rdr.bstr(), "lisa\nmaggie\nbart");
+            assert_eq!(rdr.absolute_byte_offset(), 6);
+            assert!(!rdr.lookahead(4).unwrap());
+            assert_eq!(rdr.bstr(), "lisa\nmaggie\nbart");
+
+            // Reading on after a lookahead picks up where it left off.
+            rdr.consume(5);
+            let mut got = vec![];
+            while rdr.fill().unwrap() {
+                got.push_str(rdr.buffer());
+                rdr.consume_all();
+            }
+            assert_eq!(got.as_bstr(), "maggie\nbart");
+        }
+    }
+
+    #[test]
+    fn buffer_lookahead_binary_quit() {
+        let mut linebuf = LineBufferBuilder::new()
+            .capacity(4)
+            .binary_detection(BinaryDetection::Quit(b'\x00'))
+            .build();
+        let mut rdr = LineBufferReader::new("homer\nlisa\n\x00bart\n".as_bytes(), &mut linebuf);
+        assert!(!rdr.lookahead(3).unwrap());
+        assert_eq!(rdr.bstr(), "homer\nlisa\n");
+    }
+
+    #[test]
+    fn buffer_lookahead_fragment() {
+        /// A reader that returns at most two bytes per read.
+        struct Trickle<'a>(&'a [u8]);
+
+        impl io::Read for Trickle<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let n = cmp::min(cmp::min(2, buf.len()), self.0.len());
+                buf[..n].copy_from_slice(&self.0[..n]);
+                self.0 = &self.0[n..];
+                Ok(n)
+            }
+        }
+
+        let bytes = format!("homer\nlisa\nmaggie\n{}\nbart\n", "x".repeat(40));
+        let mut linebuf = LineBufferBuilder::new()
+            .capacity(4)
+            .buffer_alloc(BufferAllocation::Fragment(10))
+            .build();
+        let mut rdr = LineBufferReader::new(Trickle(bytes.as_bytes()), &mut linebuf);
+        assert!(rdr.fill().unwrap());
+        assert!(rdr.lookahead(3).unwrap());
+        assert!(rdr.buffer().starts_with(b"homer\nlisa\nmaggie\n"));
+        assert!(rdr.lookahead(3).unwrap());
+
+        // The next line is oversized, so a fourth line can't be buffered
+        // whole, and the buffer stays bounded.
+        assert!(!rdr.lookahead(4).unwrap());
+        assert_eq!(rdr.bstr(), "homer\nlisa\nmaggie\n");
+        assert!(rdr.high_water_mark() < 64);
+
+        let mut got = vec![];
+        while rdr.fill().unwrap() {
+            got.push_str(rdr.buffer());
+            rdr.consume_all();
+        }
+        assert_eq!(got.as_bstr(), bytes.as_bytes().as_bstr());
+    }
+
+    #[test]
+    fn buffer_fill_policy_lines() {
+        let records: String = (0..25)
+            .map(|i| format!("@r{}\nACGT\n+\n{}\n", i, "I".repeat(i % 5)))
+            .collect();
+        for &capacity in [1, 7, 64, 10_000].iter() {
+            let mut linebuf = LineBufferBuilder::new()
+                .capacity(capacity)
+                .fill_policy(FillPolicy::Lines(4))
+                .build();
+            let mut rdr = LineBufferReader::new(records.as_bytes(), &mut linebuf);
+            let mut got = vec![];
+            while rdr.fill().unwrap() {
+                assert!(!rdr.at_eof());
+                let lines = lines::count(rdr.buffer(), LineTerminator::default());
+                assert!(lines > 0 && lines % 4 == 0, "capacity {}", capacity);
+                assert!(rdr.buffer().starts_with(b"@r"));
+                got.push_str(rdr.buffer());
+                rdr.consume_all();
+            }
+            assert!(rdr.at_eof());
+            assert_eq!(got.as_bstr(), records.as_bytes().as_bstr());
+        }
+    }
+
+    #[test]
+    fn buffer_fill_policy_eof() {
+        let bytes = "a\nb\nc\nd\ne\nf";
+        let mut linebuf = LineBufferBuilder::new()
+            .capacity(2)
+            .fill_policy(FillPolicy::Lines(4))
+            .build();
+        let mut rdr = LineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+        assert!(rdr.fill().unwrap());
+        assert_eq!(rdr.bstr(), "a\nb\nc\nd\n");
+        assert!(!rdr.at_eof());
+        rdr.consume_all();
+        assert!(rdr.fill().unwrap());
+        assert_eq!(rdr.bstr(), "e\nf");
+        assert!(rdr.at_eof());
+        rdr.consume_all();
+        assert!(!rdr.fill().unwrap());
+    }
+
+    #[test]
+    fn buffer_fill_policy_bytes() {
+        let bytes = "homer\nlisa\nmaggie\nbart\nmarge\n";
+        let mut linebuf = LineBufferBuilder::new()
+            .capacity(1)
+            .fill_policy(FillPolicy::Bytes(10))
+            .build();
+        let mut rdr = LineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+        let mut got = vec![];
+        while rdr.fill().unwrap() {
+            assert!(rdr.buffer().len() >= 10 || rdr.at_eof());
+            assert!(rdr.buffer().ends_with(b"\n"));
+            got.push_str(rdr.buffer());
+            rdr.consume_all();
+        }
+        assert_eq!(got.as_bstr(), bytes);
+    }
+
+    #[test]
+    fn buffer_bufread() {
+        use std::io::{BufRead, Read};
+
+        let bytes = "homer\nlisa\nmaggie\nbart";
+        for &capacity in [1, 4, 1000].iter() {
+            let mut linebuf = LineBufferBuilder::new().capacity(capacity).build();
+            let mut rdr = LineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+            let window = rdr.fill_buf().unwrap();
+            assert!(window.ends_with(b"\n"));
+            assert!(window.starts_with(b"homer\n"));
+
+            let mut line = vec![];
+            rdr.read_until(b'\n', &mut line).unwrap();
+            assert_eq!(line.as_bstr(), "homer\n");
+            let mut small = [0; 3];
+            rdr.read_exact(&mut small).unwrap();
+            assert_eq!(&small, b"lis");
+            assert_eq!(rdr.absolute_byte_offset(), 9);
+            let lines: Vec<String> = rdr.lines().map(|line| line.unwrap()).collect();
+            assert_eq!(lines, vec!["a", "maggie", "bart"]);
+        }
+    }
+
+    #[test]
+    fn buffer_read_error() {
+        use std::io::Read;
+
+        let mut linebuf = LineBufferBuilder::new()
+            .capacity(4)
+            .buffer_alloc(BufferAllocation::Error(0))
+            .build();
+        let mut rdr = LineBufferReader::new("homer\n".as_bytes(), &mut linebuf);
+        let err = rdr.read_to_end(&mut vec![]).unwrap_err();
+        assert!(matches!(
+            err.get_ref().unwrap().downcast_ref::<Error>(),
+            Some(&Error::AllocationLimit { .. })
+        ));
+    }
+
+    #[test]
+    fn buffer_seek() {
+        let bytes = "homer\nlisa\nmaggie\nbart\n";
+        let mut linebuf = LineBufferBuilder::new()
+            .capacity(4)
+            .line_number(true)
+            .build();
+        let mut rdr = LineBufferReader::new(io::Cursor::new(bytes), &mut linebuf);
+        while rdr.fill().unwrap() {
+            rdr.consume_all();
+        }
+
+        rdr.seek_to(11).unwrap();
+        assert_eq!(rdr.absolute_byte_offset(), 11);
+        rdr.set_line_number(3);
+        assert!(rdr.fill().unwrap());
+        assert!(rdr.buffer().starts_with(b"maggie\n"));
+        assert_eq!(rdr.absolute_byte_offset(), 11);
+        assert_eq!(rdr.line_number(), Some(3));
+
+        assert_eq!(rdr.seek_to_line_start(7).unwrap(), 11);
+        assert_eq!(rdr.line_number(), Some(1));
+        assert!(rdr.fill().unwrap());
+        assert!(rdr.buffer().starts_with(b"maggie\n"));
+
+        assert_eq!(rdr.seek_to_line_start(6).unwrap(), 6);
+        let mut got = vec![];
+        while rdr.fill().unwrap() {
+            got.push_str(rdr.buffer());
+            rdr.consume_all();
+        }
+        assert_eq!(got.as_bstr(), "lisa\nmaggie\nbart\n");
+        assert_eq!(rdr.absolute_byte_offset(), 23);
+    }
+
+    #[test]
+    fn buffer_shrink() {
+        let bytes = format!("homer\n{}\nlisa\nmaggie\n", "x".repeat(100));
+        let mut linebuf = LineBufferBuilder::new()
+            .capacity(8)
+            .buffer_shrink(BufferShrink::Threshold(0))
+            .build();
+        let mut rdr = LineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+
+        let mut got = vec![];
+        while rdr.fill().unwrap() {
+            got.push_str(rdr.buffer());
+            rdr.consume_all();
+        }
+        assert_eq!(bytes, got.as_bstr());
+        assert!(rdr.high_water_mark() >= 101);
+        assert_eq!(linebuf.buf.len(), 8);
+        assert_eq!(linebuf.buf.capacity(), 8);
+    }
+
+    #[test]
+    fn buffer_shrink_threshold() {
+        let bytes = format!("{}\nlisa\n", "x".repeat(20));
+        let mut linebuf = LineBufferBuilder::new()
+            .capacity(8)
+            .buffer_shrink(BufferShrink::Threshold(1000))
+            .build();
+        let mut rdr = LineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+        while rdr.fill().unwrap() {
+            rdr.consume_all();
+        }
+        let high_water_mark = rdr.high_water_mark();
+        assert!(high_water_mark > 8);
+        assert_eq!(linebuf.buf.len(), high_water_mark);
+    }
+
+    #[test]
+    fn buffer_shrink_never() {
+        let bytes = format!("{}\nlisa\n", "x".repeat(100));
+        let mut linebuf = LineBufferBuilder::new().capacity(8).build();
+        let mut rdr = LineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+        while rdr.fill().unwrap() {
+            rdr.consume_all();
+        }
+        let high_water_mark = rdr.high_water_mark();
+
+        // The buffer keeps its size and its high water mark across readers.
+        let rdr = LineBufferReader::new("lisa\n".as_bytes(), &mut linebuf);
+        assert_eq!(rdr.high_water_mark(), high_water_mark);
+        assert_eq!(linebuf.buf.len(), high_water_mark);
+    }
+
+    #[test]
+    fn buffer_binary_none() {
+        let mut bytes = s(SHERLOCK);
+        bytes.push('\x00');
+        let mut linebuf = LineBufferBuilder::new().build();
+        let mut rdr = LineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+
+        let mut got = vec![];
+        while rdr.fill().unwrap() {
+            got.push_str(rdr.buffer());
+            rdr.consume_all();
+        }
+        assert_eq!(got.as_bstr(), bytes.as_str());
+        assert_eq!(rdr.absolute_byte_offset(), bytes.len() as u64);
+        assert_eq!(rdr.binary_byte_offset(), None);
+    }
+
+    #[test]
+    fn buffer_binary_quit1() {
+        let bytes = "homer\nli\x00sa\nmaggie\n";
+        let mut linebuf = LineBufferBuilder::new()
+            .binary_detection(BinaryDetection::Quit(b'\x00'))
+            .build();
+        let mut rdr = LineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+
+        assert!(rdr.buffer().is_empty());
+
+        assert!(rdr.fill().unwrap());
+        assert_eq!(rdr.bstr(), "homer\nli");
+        rdr.consume_all();
+
+        assert!(!rdr.fill().unwrap());
+        assert_eq!(rdr.absolute_byte_offset(), 8);
+        assert_eq!(rdr.binary_byte_offset(), Some(8));
+    }
+
+    #[test]
+    fn buffer_binary_quit2() {
+        let bytes = "\x00homer\nlisa\nmaggie\n";
+        let mut linebuf = LineBufferBuilder::new()
+            .binary_detection(BinaryDetection::Quit(b'\x00'))
+            .build();
+        let mut rdr = LineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+
+        assert!(!rdr.fill().unwrap());
+        assert_eq!(rdr.bstr(), "");
+        assert_eq!(rdr.absolute_byte_offset(), 0);
+        assert_eq!(rdr.binary_byte_offset(), Some(0));
+    }
+
+    #[test]
+    fn buffer_binary_quit3() {
+        let bytes = "homer\nlisa\nmaggie\n\x00";
+        let mut linebuf = LineBufferBuilder::new()
+            .binary_detection(BinaryDetection::Quit(b'\x00'))
+            .build();
+        let mut rdr = LineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+
+        assert!(rdr.fill().unwrap());
+        assert_eq!(rdr.bstr(), "homer\nlisa\nmaggie\n");
+        rdr.consume_all();
+
+        assert!(!rdr.fill().unwrap());
+        assert_eq!(rdr.absolute_byte_offset(), bytes.len() as u64 - 1);
+        assert_eq!(rdr.binary_byte_offset(), Some(bytes.len() as u64 - 1));
+    }
+
+    #[test]
+    fn buffer_binary_quit_small_capacity() {
+        let bytes = "homer\nlisa\nmag\x00gie\n";
+        let mut linebuf = LineBufferBuilder::new()
+            .capacity(1)
+            .binary_detection(BinaryDetection::Quit(b'\x00'))
+            .build();
+        let mut rdr = LineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+
+        let mut got = vec![];
+        while rdr.fill().unwrap() {
+            got.push_str(rdr.buffer());
+            rdr.consume_all();
+        }
+        assert_eq!(got.as_bstr(), "homer\nlisa\nmag");
+        assert_eq!(rdr.absolute_byte_offset(), 14);
+        assert_eq!(rdr.binary_byte_offset(), Some(14));
+    }
+
+    #[test]
+    fn buffer_binary_convert1() {
+        let bytes = "homer\nli\x00sa\nmaggie\n";
+        let mut linebuf = LineBufferBuilder::new()
+            .binary_detection(BinaryDetection::Convert(b'\x00'))
+            .build();
+        let mut rdr = LineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+
+        assert!(rdr.fill().unwrap());
+        assert_eq!(rdr.bstr(), "homer\nli\nsa\nmaggie\n");
+        rdr.consume_all();
+
+        assert!(!rdr.fill().unwrap());
+        assert_eq!(rdr.absolute_byte_offset(), bytes.len() as u64);
+        assert_eq!(rdr.binary_byte_offset(), Some(8));
+    }
+
+    #[test]
+    fn buffer_binary_convert2() {
+        let bytes = "\x00homer\nlisa\x00\x00maggie\n\x00";
+        let mut linebuf = LineBufferBuilder::new()
+            .binary_detection(BinaryDetection::Convert(b'\x00'))
+            .build();
+        let mut rdr = LineBufferReader::new(bytes.as_bytes(), &mut linebuf);
+
+        assert!(rdr.fill().unwrap());
+        assert_eq!(rdr.bstr(), "\nhomer\nlisa\n\nmaggie\n\n");
+        rdr.consume_all();
+
+        assert!(!rdr.fill().unwrap());
+        assert_eq!(rdr.absolute_byte_offset(), bytes.len() as u64);
+        assert_eq!(rdr.binary_byte_offset(), Some(0));
+    }
 }
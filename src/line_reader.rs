@@ -0,0 +1,279 @@
+/*!
+Read lines one at a time without writing the fill and consume loop by hand.
+
+A `LineReader` owns a reader and a `LineBuffer`, and hands out one line at a
+time from the buffer. Lines borrow from the reader, so they must be dropped
+(or copied) before asking for the next one. Line terminators are considered
+part of the line they terminate.
+
+```no_run
+use ripline::line_reader::LineReader;
+use std::{fs::File, io::Write};
+
+# fn example() -> std::io::Result<()> {
+let mut out = std::io::stdout();
+let mut rdr = LineReader::new(File::open("big.tsv")?);
+rdr.for_each_line(|line| {
+    out.write_all(line)?;
+    Ok(true)
+})?;
+# Ok(())
+# }
+```
+*/
+
+use std::io;
+
+use bstr::ByteSlice;
+
+use crate::line_buffer::{LineBuffer, LineBufferBuilder};
+
+/// Where a line was found in the input.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LineMeta {
+    absolute_offset: u64,
+    line_number: Option<u64>,
+}
+
+impl LineMeta {
+    /// The absolute byte offset of the start of the line.
+    pub fn absolute_offset(&self) -> u64 {
+        self.absolute_offset
+    }
+
+    /// The line number of the line, starting at `1`.
+    ///
+    /// This is only available when line numbers are enabled with
+    /// `LineBufferBuilder::line_number`, and is `None` otherwise.
+    pub fn line_number(&self) -> Option<u64> {
+        self.line_number
+    }
+}
+
+/// A reader that yields one line at a time.
+#[derive(Debug)]
+pub struct LineReader<R> {
+    rdr: R,
+    line_buffer: LineBuffer,
+    /// The length of the line handed out last, which is consumed when the
+    /// next line is requested.
+    pending: usize,
+}
+
+impl<R: io::Read> LineReader<R> {
+    /// Create a new line reader over `rdr` with a default line buffer.
+    pub fn new(rdr: R) -> LineReader<R> {
+        LineReader::with_line_buffer(rdr, LineBufferBuilder::new().build())
+    }
+
+    /// Create a new line reader over `rdr` that uses the given line buffer.
+    ///
+    /// The line buffer is cleared before use. Its configuration, like the
+    /// line terminator or the buffer allocation strategy, applies to the
+    /// lines handed out by this reader. With `BufferAllocation::Fragment`,
+    /// each piece of an oversized line is handed out as a line of its own.
+    pub fn with_line_buffer(rdr: R, mut line_buffer: LineBuffer) -> LineReader<R> {
+        line_buffer.clear();
+        LineReader {
+            rdr,
+            line_buffer,
+            pending: 0,
+        }
+    }
+
+    /// Return a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.rdr
+    }
+
+    /// Return a mutable reference to the underlying reader.
+    ///
+    /// Reading from the underlying reader directly skips over data that
+    /// hasn't been buffered yet.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.rdr
+    }
+
+    /// Unwrap this line reader, returning the underlying reader and line
+    /// buffer. Any buffered data is lost.
+    pub fn into_inner(self) -> (R, LineBuffer) {
+        (self.rdr, self.line_buffer)
+    }
+
+    /// Return the next line, or `None` at EOF.
+    ///
+    /// Errors are those of `LineBufferReader::fill`, converted into an
+    /// `io::Error`.
+    pub fn next_line(&mut self) -> io::Result<Option<&[u8]>> {
+        if !self.advance()? {
+            return Ok(None);
+        }
+        Ok(Some(&self.line_buffer.buffer()[..self.pending]))
+    }
+
+    /// Return the next line along with where it was found, or `None` at EOF.
+    pub fn next_line_with_meta(&mut self) -> io::Result<Option<(LineMeta, &[u8])>> {
+        if !self.advance()? {
+            return Ok(None);
+        }
+        let meta = LineMeta {
+            absolute_offset: self.line_buffer.absolute_byte_offset(),
+            line_number: self.line_buffer.line_number(),
+        };
+        Ok(Some((meta, &self.line_buffer.buffer()[..self.pending])))
+    }
+
+    /// Return an iterator over the remaining lines along with where they
+    /// were found.
+    ///
+    /// Like `io::BufRead::lines`, each line is copied into a `Vec<u8>` of its
+    /// own, so that it can outlive the next call to `next`. Use
+    /// `next_line_with_meta` or `for_each_line_with_meta` to avoid the copy.
+    pub fn lines_with_meta(&mut self) -> LinesWithMeta<'_, R> {
+        LinesWithMeta { rdr: self }
+    }
+
+    /// Call `f` on every remaining line, until `f` returns `false` or an
+    /// error.
+    pub fn for_each_line<F>(&mut self, mut f: F) -> io::Result<()>
+    where
+        F: FnMut(&[u8]) -> io::Result<bool>,
+    {
+        while let Some(line) = self.next_line()? {
+            if !f(line)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Call `f` on every remaining line along with where it was found, until
+    /// `f` returns `false` or an error.
+    pub fn for_each_line_with_meta<F>(&mut self, mut f: F) -> io::Result<()>
+    where
+        F: FnMut(LineMeta, &[u8]) -> io::Result<bool>,
+    {
+        while let Some((meta, line)) = self.next_line_with_meta()? {
+            if !f(meta, line)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Consume the line handed out last and make sure the next one is
+    /// buffered. On success, `pending` is set to its length.
+    fn advance(&mut self) -> io::Result<bool> {
+        self.line_buffer.consume(self.pending);
+        self.pending = 0;
+        if self.line_buffer.buffer().is_empty() && !self.line_buffer.fill(&mut self.rdr)? {
+            return Ok(false);
+        }
+        let buf = self.line_buffer.buffer();
+        self.pending = match buf.find_byte(self.line_buffer.line_terminator()) {
+            Some(i) => i + 1,
+            None => buf.len(),
+        };
+        Ok(true)
+    }
+}
+
+/// An iterator over the lines of a `LineReader` along with where they were
+/// found.
+///
+/// This is created by `LineReader::lines_with_meta`.
+#[derive(Debug)]
+pub struct LinesWithMeta<'r, R> {
+    rdr: &'r mut LineReader<R>,
+}
+
+impl<'r, R: io::Read> Iterator for LinesWithMeta<'r, R> {
+    type Item = io::Result<(LineMeta, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<io::Result<(LineMeta, Vec<u8>)>> {
+        match self.rdr.next_line_with_meta() {
+            Ok(Some((meta, line))) => Some(Ok((meta, line.to_vec()))),
+            Ok(None) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_buffer::BufferAllocation;
+
+    fn lines(text: &str, capacity: usize) -> Vec<String> {
+        let line_buffer = LineBufferBuilder::new().capacity(capacity).build();
+        let mut rdr = LineReader::with_line_buffer(text.as_bytes(), line_buffer);
+        let mut got = vec![];
+        while let Some(line) = rdr.next_line().unwrap() {
+            got.push(line.to_str().unwrap().to_string());
+        }
+        assert!(rdr.next_line().unwrap().is_none());
+        got
+    }
+
+    #[test]
+    fn line_reader_basics() {
+        for &capacity in [1, 4, 1000].iter() {
+            assert_eq!(lines("", capacity), Vec::<String>::new());
+            assert_eq!(lines("\n", capacity), vec!["\n"]);
+            assert_eq!(
+                lines("homer\nlisa\n\nmaggie", capacity),
+                vec!["homer\n", "lisa\n", "\n", "maggie"]
+            );
+        }
+    }
+
+    #[test]
+    fn line_reader_meta() {
+        let line_buffer = LineBufferBuilder::new()
+            .capacity(4)
+            .line_number(true)
+            .buffer_alloc(BufferAllocation::Skip(5))
+            .build();
+        let text = "homer\nlisa\nmaggie\nbart\n";
+        let mut rdr = LineReader::with_line_buffer(text.as_bytes(), line_buffer.clone());
+        let mut got = vec![];
+        rdr.for_each_line_with_meta(|meta, line| {
+            got.push((
+                meta.absolute_offset(),
+                meta.line_number().unwrap(),
+                line.to_vec(),
+            ));
+            Ok(true)
+        })
+        .unwrap();
+        let expected = vec![
+            (0, 1, b"homer\n".to_vec()),
+            (6, 2, b"lisa\n".to_vec()),
+            (18, 4, b"bart\n".to_vec()),
+        ];
+        assert_eq!(got, expected);
+
+        let mut rdr = LineReader::with_line_buffer(text.as_bytes(), line_buffer);
+        let got: Vec<_> = rdr
+            .lines_with_meta()
+            .map(|result| {
+                let (meta, line) = result.unwrap();
+                (meta.absolute_offset(), meta.line_number().unwrap(), line)
+            })
+            .collect();
+        assert_eq!(got, expected);
+    }
+
+    #[test]
+    fn line_reader_stops_early() {
+        let mut rdr = LineReader::new("homer\nlisa\nmaggie\n".as_bytes());
+        let mut count = 0;
+        rdr.for_each_line(|_| {
+            count += 1;
+            Ok(count < 2)
+        })
+        .unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(rdr.next_line().unwrap(), Some(&b"maggie\n"[..]));
+    }
+}
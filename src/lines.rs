@@ -11,27 +11,41 @@ use bytecount;
 /// An iterator over lines in a particular slice of bytes.
 ///
 /// Line terminators are considered part of the line they terminate. All lines
-/// yielded by the iterator are guaranteed to be non-empty.
+/// yielded by the iterator are guaranteed to be non-empty, unless terminators
+/// are being stripped via `strip_terminator`.
+///
+/// When the line terminator is CRLF, a lone `\n` also ends a line.
 ///
 /// `'b` refers to the lifetime of the underlying bytes.
 #[derive(Debug)]
 pub struct LineIter<'b> {
     bytes: &'b [u8],
     stepper: LineStep,
-    line_term: u8,
+    line_term: LineTerminator,
+    strip: bool,
 }
 
 impl<'b> LineIter<'b> {
     /// Create a new line iterator that yields lines in the given bytes that
     /// are terminated by `line_term`.
-    pub fn new(line_term: u8, bytes: &'b [u8]) -> LineIter<'b> {
+    pub fn new(line_term: LineTerminator, bytes: &'b [u8]) -> LineIter<'b> {
         LineIter {
             line_term,
             bytes,
             stepper: LineStep::new(line_term, 0, bytes.len()),
+            strip: false,
         }
     }
 
+    /// When enabled, lines are yielded without their line terminator.
+    ///
+    /// If the line terminator is CRLF, then both `\r\n` and a lone `\n` are
+    /// removed. This is disabled by default.
+    pub fn strip_terminator(mut self, yes: bool) -> LineIter<'b> {
+        self.strip = yes;
+        self
+    }
+
     pub fn swap_bytes(&mut self, new_bytes: &'b [u8]) {
         self.bytes = new_bytes;
         self.stepper = LineStep::new(self.line_term, 0, new_bytes.len());
@@ -42,7 +56,12 @@ impl<'b> Iterator for LineIter<'b> {
     type Item = &'b [u8];
 
     fn next(&mut self) -> Option<&'b [u8]> {
-        self.stepper.next_match(self.bytes).map(|m| &self.bytes[m])
+        let line = &self.bytes[self.stepper.next_match(self.bytes)?];
+        if self.strip {
+            Some(without_terminator(line, self.line_term))
+        } else {
+            Some(line)
+        }
     }
 }
 
@@ -55,9 +74,11 @@ impl<'b> Iterator for LineIter<'b> {
 ///
 /// Line terminators are considered part of the line they terminate. All lines
 /// yielded by the iterator are guaranteed to be non-empty.
+///
+/// When the line terminator is CRLF, a lone `\n` also ends a line.
 #[derive(Debug)]
 pub struct LineStep {
-    line_term: u8,
+    line_term: LineTerminator,
     pos: usize,
     end: usize,
 }
@@ -70,7 +91,7 @@ impl LineStep {
     /// same slice must be provided to each call.
     ///
     /// This panics if `start` is not less than or equal to `end`.
-    pub fn new(line_term: u8, start: usize, end: usize) -> LineStep {
+    pub fn new(line_term: LineTerminator, start: usize, end: usize) -> LineStep {
         LineStep {
             line_term,
             pos: start,
@@ -78,6 +99,11 @@ impl LineStep {
         }
     }
 
+    /// Return the line terminator used by this iterator.
+    pub fn line_terminator(&self) -> LineTerminator {
+        self.line_term
+    }
+
     /// Return the start and end position of the next line in the given bytes.
     ///
     /// The caller must past exactly the same slice of bytes for each call to
@@ -98,7 +124,7 @@ impl LineStep {
     #[inline(always)]
     fn next_impl(&mut self, mut bytes: &[u8]) -> Option<(usize, usize)> {
         bytes = &bytes[..self.end];
-        match bytes[self.pos..].find_byte(self.line_term) {
+        match bytes[self.pos..].find_byte(self.line_term.as_byte()) {
             None => {
                 if self.pos < bytes.len() {
                     let m = (self.pos, bytes.len());
@@ -122,20 +148,29 @@ impl LineStep {
 }
 
 /// Count the number of occurrences of `line_term` in `bytes`.
-pub fn count(bytes: &[u8], line_term: u8) -> u64 {
-    bytecount::count(bytes, line_term) as u64
+///
+/// If `line_term` is CRLF, then this counts occurrences of `\n`, since every
+/// CRLF terminated line also ends with `\n`.
+pub fn count(bytes: &[u8], line_term: LineTerminator) -> u64 {
+    bytecount::count(bytes, line_term.as_byte()) as u64
 }
 
 /// Given a line that possibly ends with a terminator, return that line without
 /// the terminator.
+///
+/// If `line_term` is CRLF, then a lone `\n` is also stripped.
 #[inline(always)]
 pub fn without_terminator(bytes: &[u8], line_term: LineTerminator) -> &[u8] {
-    let line_term = line_term.as_bytes();
-    let start = bytes.len().saturating_sub(line_term.len());
-    if bytes.get(start..) == Some(line_term) {
-        return &bytes[..bytes.len() - line_term.len()];
+    if line_term.is_crlf() {
+        return match bytes {
+            [line @ .., b'\r', b'\n'] | [line @ .., b'\n'] => line,
+            _ => bytes,
+        };
+    }
+    match bytes.split_last() {
+        Some((&last, line)) if last == line_term.as_byte() => line,
+        _ => bytes,
     }
-    bytes
 }
 
 /// Return the start and end offsets of the lines containing the given range
@@ -143,7 +178,8 @@ pub fn without_terminator(bytes: &[u8], line_term: LineTerminator) -> &[u8] {
 ///
 /// Line terminators are considered part of the line they terminate.
 #[inline(always)]
-pub fn locate(bytes: &[u8], line_term: u8, range: Match) -> Match {
+pub fn locate(bytes: &[u8], line_term: LineTerminator, range: Match) -> Match {
+    let line_term = line_term.as_byte();
     let line_start = bytes[..range.start()]
         .rfind_byte(line_term)
         .map_or(0, |i| i + 1);
@@ -165,8 +201,8 @@ pub fn locate(bytes: &[u8], line_term: u8, range: Match) -> Match {
 ///
 /// If `bytes` ends with a line terminator, then the terminator itself is
 /// considered part of the last line.
-pub fn preceding(bytes: &[u8], line_term: u8, count: usize) -> usize {
-    preceding_by_pos(bytes, bytes.len(), line_term, count)
+pub fn preceding(bytes: &[u8], line_term: LineTerminator, count: usize) -> usize {
+    preceding_by_pos(bytes, bytes.len(), line_term.as_byte(), count)
 }
 
 /// Returns the minimal starting offset of the line that occurs `count` lines
@@ -223,7 +259,7 @@ and exhibited clearly, with a label attached.\
 
     fn lines(text: &str) -> Vec<&str> {
         let mut results = vec![];
-        let mut it = LineStep::new(b'\n', 0, text.len());
+        let mut it = LineStep::new(LineTerminator::default(), 0, text.len());
         while let Some(m) = it.next_match(text.as_bytes()) {
             results.push(&text[m]);
         }
@@ -232,7 +268,7 @@ and exhibited clearly, with a label attached.\
 
     fn line_ranges(text: &str) -> Vec<Range<usize>> {
         let mut results = vec![];
-        let mut it = LineStep::new(b'\n', 0, text.len());
+        let mut it = LineStep::new(LineTerminator::default(), 0, text.len());
         while let Some(m) = it.next_match(text.as_bytes()) {
             results.push(m.start()..m.end());
         }
@@ -244,15 +280,28 @@ and exhibited clearly, with a label attached.\
     }
 
     fn loc(text: &str, start: usize, end: usize) -> Match {
-        locate(text.as_bytes(), b'\n', Match::new(start, end))
+        locate(
+            text.as_bytes(),
+            LineTerminator::default(),
+            Match::new(start, end),
+        )
     }
 
     #[test]
     fn line_count() {
-        assert_eq!(0, count(b"", b'\n'));
-        assert_eq!(1, count(b"\n", b'\n'));
-        assert_eq!(2, count(b"\n\n", b'\n'));
-        assert_eq!(2, count(b"a\nb\nc", b'\n'));
+        let lt = LineTerminator::default();
+        assert_eq!(0, count(b"", lt));
+        assert_eq!(1, count(b"\n", lt));
+        assert_eq!(2, count(b"\n\n", lt));
+        assert_eq!(2, count(b"a\nb\nc", lt));
+    }
+
+    #[test]
+    fn line_count_crlf() {
+        let lt = LineTerminator::crlf();
+        assert_eq!(0, count(b"\r", lt));
+        assert_eq!(1, count(b"\r\n", lt));
+        assert_eq!(2, count(b"a\r\nb\nc", lt));
     }
 
     #[test]
@@ -336,10 +385,50 @@ and exhibited clearly, with a label attached.\
 
     #[test]
     fn line_iter_empty() {
-        let mut it = LineStep::new(b'\n', 0, 0);
+        let mut it = LineStep::new(LineTerminator::default(), 0, 0);
         assert_eq!(it.next(b"abc"), None);
     }
 
+    #[test]
+    fn line_iter_crlf() {
+        let lines = |text| LineIter::new(LineTerminator::crlf(), text).collect::<Vec<_>>();
+        assert_eq!(lines(b"abc\r\nxyz"), vec![&b"abc\r\n"[..], b"xyz"]);
+        assert_eq!(lines(b"abc\r\nxyz\n"), vec![&b"abc\r\n"[..], b"xyz\n"]);
+        assert_eq!(lines(b"a\rbc\r\n\r\n"), vec![&b"a\rbc\r\n"[..], b"\r\n"]);
+    }
+
+    #[test]
+    fn line_iter_strip() {
+        let lines = |lt, text| {
+            LineIter::new(lt, text)
+                .strip_terminator(true)
+                .collect::<Vec<_>>()
+        };
+        let lf = LineTerminator::default();
+        let crlf = LineTerminator::crlf();
+        assert_eq!(lines(lf, b"abc\nxyz"), vec![&b"abc"[..], b"xyz"]);
+        assert_eq!(lines(lf, b"abc\r\n\n"), vec![&b"abc\r"[..], b""]);
+        assert_eq!(lines(crlf, b"abc\r\nxyz\n"), vec![&b"abc"[..], b"xyz"]);
+        assert_eq!(
+            lines(crlf, b"abc\r\n\r\nxyz\r"),
+            vec![&b"abc"[..], b"", b"xyz\r"]
+        );
+    }
+
+    #[test]
+    fn strip_terminator() {
+        let lf = LineTerminator::default();
+        let crlf = LineTerminator::crlf();
+        assert_eq!(without_terminator(b"abc\n", lf), b"abc");
+        assert_eq!(without_terminator(b"abc\r\n", lf), b"abc\r");
+        assert_eq!(without_terminator(b"abc", lf), b"abc");
+        assert_eq!(without_terminator(b"", lf), b"");
+        assert_eq!(without_terminator(b"abc\r\n", crlf), b"abc");
+        assert_eq!(without_terminator(b"abc\n", crlf), b"abc");
+        assert_eq!(without_terminator(b"abc\r", crlf), b"abc\r");
+        assert_eq!(without_terminator(b"\n", crlf), b"");
+    }
+
     #[test]
     fn preceding_lines_doc() {
         // These are the examples mentions in the documentation of `preceding`.
@@ -350,6 +439,15 @@ and exhibited clearly, with a label attached.\
         assert_eq!(0, preceding_by_pos(bytes, 8, b'\n', 1));
     }
 
+    #[test]
+    fn preceding_lines_crlf() {
+        let bytes = b"abc\r\nxyz\r\n";
+        let crlf = LineTerminator::crlf();
+        assert_eq!(5, preceding(bytes, crlf, 0));
+        assert_eq!(0, preceding(bytes, crlf, 1));
+        assert_eq!(m(5, 10), locate(bytes, crlf, m(6, 7)));
+    }
+
     #[test]
     fn preceding_lines_sherlock() {
         let t = SHERLOCK;
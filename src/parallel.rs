@@ -0,0 +1,146 @@
+/*!
+Parallel line iteration over in-memory data, such as memory-mapped files.
+
+The data is cut into roughly equal chunks whose boundaries are moved forward to
+the next line boundary, so every line falls into exactly one chunk. The chunks
+are then handed out as a rayon parallel iterator of `LineIter`s. Since that
+iterator is indexed, collecting per-chunk results preserves input order.
+
+```no_run
+use rayon::prelude::*;
+use ripline::{parallel, LineTerminator};
+
+# fn example() -> std::io::Result<()> {
+// SAFETY: the file is not modified while it is mapped.
+let mmap = unsafe { parallel::map_file("big.tsv")? };
+let lines_per_chunk: Vec<usize> = parallel::par_chunks(&mmap, LineTerminator::default(), 64)
+    .map(|lines| lines.count())
+    .collect();
+# Ok(())
+# }
+```
+*/
+
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use memmap2::Mmap;
+use rayon::prelude::*;
+
+use crate::lines::{locate, LineIter};
+use crate::{LineTerminator, Match};
+
+/// The target size of a chunk for `par_lines`.
+const DEFAULT_CHUNK_SIZE: usize = 1 << 20; // 1 MB
+
+/// Memory map the file at the given path.
+///
+/// # Safety
+///
+/// The mapped bytes must not be modified, for example by truncating or
+/// writing to the file from this or another process, while the map is alive.
+/// See `memmap2::Mmap::map` for details.
+pub unsafe fn map_file<P: AsRef<Path>>(path: P) -> io::Result<Mmap> {
+    let file = File::open(path)?;
+    Mmap::map(&file)
+}
+
+/// Split `bytes` into at most `chunks` ranges of roughly equal size, each of
+/// which starts and ends on a line boundary.
+///
+/// The ranges are returned in order, are non-empty and together cover all of
+/// `bytes`. A value of `0` for `chunks` is treated as `1`.
+pub fn chunk_ranges(bytes: &[u8], line_term: LineTerminator, chunks: usize) -> Vec<Match> {
+    let chunks = chunks.max(1);
+    let mut ranges = Vec::with_capacity(chunks);
+    let mut start = 0;
+    for i in 1..=chunks {
+        let mut end = (bytes.len() as u128 * i as u128 / chunks as u128) as usize;
+        if end <= start {
+            continue;
+        }
+        // Move the boundary forward to the end of the line it falls in,
+        // unless it already sits right at the start of a line.
+        let line = locate(bytes, line_term, Match::zero(end));
+        if line.start() != end {
+            end = line.end();
+        }
+        if end > start {
+            ranges.push(Match::new(start, end));
+            start = end;
+        }
+    }
+    ranges
+}
+
+/// Return an indexed parallel iterator over at most `chunks` line aligned
+/// chunks of `bytes`, each yielded as a `LineIter`.
+///
+/// Because the iterator is indexed, order sensitive adapters like `collect`
+/// return per-chunk results in input order.
+pub fn par_chunks<'b>(
+    bytes: &'b [u8],
+    line_term: LineTerminator,
+    chunks: usize,
+) -> impl IndexedParallelIterator<Item = LineIter<'b>> + 'b {
+    chunk_ranges(bytes, line_term, chunks)
+        .into_par_iter()
+        .map(move |range| LineIter::new(line_term, &bytes[range]))
+}
+
+/// Return a parallel iterator over all lines in `bytes`.
+///
+/// Lines are processed in chunks of about 1 MB. Line terminators are
+/// considered part of the line they terminate.
+pub fn par_lines<'b>(
+    bytes: &'b [u8],
+    line_term: LineTerminator,
+) -> impl ParallelIterator<Item = &'b [u8]> + 'b {
+    let chunks = bytes.len() / DEFAULT_CHUNK_SIZE + 1;
+    par_chunks(bytes, line_term, chunks).flat_map_iter(|lines| lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(text: &str, chunks: usize) -> Vec<&str> {
+        chunk_ranges(text.as_bytes(), LineTerminator::default(), chunks)
+            .into_iter()
+            .map(|m| &text[m])
+            .collect()
+    }
+
+    #[test]
+    fn chunks_aligned() {
+        assert_eq!(ranges("", 4), Vec::<&str>::new());
+        assert_eq!(ranges("abc", 0), vec!["abc"]);
+        assert_eq!(ranges("a\nb\nc\nd\n", 2), vec!["a\nb\n", "c\nd\n"]);
+        assert_eq!(ranges("a\nb\nc\nd", 3), vec!["a\n", "b\n", "c\nd"]);
+        assert_eq!(ranges("aaaaaaaa\nb\n", 4), vec!["aaaaaaaa\n", "b\n"]);
+        assert_eq!(ranges("a\nb\n", 10), vec!["a\n", "b\n"]);
+    }
+
+    #[test]
+    fn chunks_cover_all_lines() {
+        let text: String = (0..1000)
+            .map(|i| format!("{}\n", "x".repeat(i % 17)))
+            .collect();
+        for &chunks in [1, 2, 7, 64, 5000].iter() {
+            let got: Vec<&[u8]> = par_chunks(text.as_bytes(), LineTerminator::default(), chunks)
+                .flat_map_iter(|lines| lines)
+                .collect();
+            let expected: Vec<&[u8]> =
+                LineIter::new(LineTerminator::default(), text.as_bytes()).collect();
+            assert_eq!(got, expected);
+        }
+    }
+
+    #[test]
+    fn lines_crlf() {
+        let text = b"abc\r\nxyz\r\n\r\nlast";
+        let count = par_lines(text, LineTerminator::crlf()).count();
+        assert_eq!(count, 4);
+    }
+}
@@ -0,0 +1,376 @@
+/*!
+Read lines on a dedicated thread and hand them to workers in chunks.
+
+This is meant for streaming inputs, like pipes or decompressors, that can't be
+memory mapped. A `Pipeline` moves a `LineBuffer` and a reader onto a reader
+thread, which fills the buffer and sends copies of its contents, each made up
+of whole lines, over a bounded channel. Any number of workers can receive
+chunks. When a chunk is dropped, its allocation is returned to a pool that the
+reader thread draws from, so a steady state pipeline doesn't allocate.
+
+Every chunk carries a sequence number, which can be used to put the results of
+the workers back in input order.
+
+```no_run
+use ripline::{line_buffer::LineBufferBuilder, pipeline::PipelineBuilder};
+use std::{fs::File, thread};
+
+# fn example() -> std::io::Result<()> {
+let line_buffer = LineBufferBuilder::new().build();
+let pipeline = PipelineBuilder::new().build(File::open("big.tsv")?, line_buffer);
+let mut counts: Vec<(u64, usize)> = thread::scope(|s| {
+    let workers: Vec<_> = (0..4)
+        .map(|_| {
+            let chunks = pipeline.receiver();
+            s.spawn(move || {
+                chunks
+                    .map(|chunk| (chunk.seq(), chunk.lines().count()))
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+    workers.into_iter().flat_map(|w| w.join().unwrap()).collect()
+});
+counts.sort_unstable();
+pipeline.finish()?;
+# Ok(())
+# }
+```
+*/
+
+use std::io;
+use std::mem;
+use std::ops;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use crate::line_buffer::{LineBuffer, LineBufferReader};
+use crate::lines::LineIter;
+use crate::LineTerminator;
+
+/// The default number of chunks that may be queued between the reader thread
+/// and the workers.
+const DEFAULT_QUEUE_LEN: usize = 16;
+
+/// A builder for constructing pipelines.
+#[derive(Clone, Debug)]
+pub struct PipelineBuilder {
+    queue_len: usize,
+}
+
+impl Default for PipelineBuilder {
+    fn default() -> PipelineBuilder {
+        PipelineBuilder::new()
+    }
+}
+
+impl PipelineBuilder {
+    /// Create a new builder for a pipeline.
+    pub fn new() -> PipelineBuilder {
+        PipelineBuilder {
+            queue_len: DEFAULT_QUEUE_LEN,
+        }
+    }
+
+    /// Set the number of chunks that may be queued up waiting for a worker.
+    ///
+    /// Once the queue is full, the reader thread blocks until a worker
+    /// receives a chunk. This bounds the memory used by chunks that haven't
+    /// been received yet to roughly `queue_len` times the capacity of the line
+    /// buffer. By default, this is set to `16`. A value of `0` is treated as
+    /// `1`.
+    pub fn queue_len(&mut self, queue_len: usize) -> &mut PipelineBuilder {
+        self.queue_len = queue_len;
+        self
+    }
+
+    /// Start a pipeline that reads from `rdr` on a new thread, using the given
+    /// `line_buffer` as an intermediate buffer.
+    ///
+    /// This does not change the binary detection behavior of the given line
+    /// buffer.
+    pub fn build<R>(&self, rdr: R, line_buffer: LineBuffer) -> Pipeline
+    where
+        R: io::Read + Send + 'static,
+    {
+        let queue_len = self.queue_len.max(1);
+        let (tx, rx) = mpsc::sync_channel(queue_len);
+        // Keep enough buffers around for a full queue plus a chunk in the
+        // hands of a few workers.
+        let pool = Pool {
+            buffers: Arc::new(Mutex::new(Vec::with_capacity(queue_len * 2))),
+            max_len: queue_len * 2,
+        };
+        let reader = thread::spawn(move || read_chunks(rdr, line_buffer, tx, pool));
+        Pipeline {
+            chunks: ChunkReceiver(Arc::new(Mutex::new(rx))),
+            reader,
+        }
+    }
+}
+
+/// A pipeline that reads chunks of lines on a dedicated thread.
+///
+/// Chunks are received with `recv`, or by handing out receivers to workers
+/// with `receiver`. Once all chunks have been received, `finish` reports
+/// whether the input was read successfully.
+#[derive(Debug)]
+pub struct Pipeline {
+    chunks: ChunkReceiver,
+    reader: thread::JoinHandle<io::Result<()>>,
+}
+
+impl Pipeline {
+    /// Start a pipeline with a default configuration.
+    pub fn new<R>(rdr: R, line_buffer: LineBuffer) -> Pipeline
+    where
+        R: io::Read + Send + 'static,
+    {
+        PipelineBuilder::new().build(rdr, line_buffer)
+    }
+
+    /// Return a new receiver of chunks from this pipeline.
+    ///
+    /// Every chunk is received by exactly one receiver.
+    pub fn receiver(&self) -> ChunkReceiver {
+        self.chunks.clone()
+    }
+
+    /// Block until the next chunk is available and return it.
+    ///
+    /// Once the input is exhausted, or reading it failed, this returns `None`.
+    pub fn recv(&self) -> Option<Chunk> {
+        self.chunks.recv()
+    }
+
+    /// Wait for the reader thread to exit, and return the error that stopped
+    /// it, if any.
+    ///
+    /// If receivers handed out by `receiver` are still alive and haven't
+    /// drained the pipeline, then this blocks until they do or are dropped.
+    /// If this pipeline is the last receiver, then unreceived chunks are
+    /// discarded and the reader thread stops early.
+    pub fn finish(self) -> io::Result<()> {
+        drop(self.chunks);
+        match self.reader.join() {
+            Ok(result) => result,
+            Err(_) => Err(io::Error::other("pipeline reader thread panicked")),
+        }
+    }
+}
+
+/// A handle for receiving chunks from a pipeline.
+///
+/// Receivers can be cloned and sent to other threads. Iterating over a
+/// receiver yields chunks until the pipeline is exhausted.
+#[derive(Clone, Debug)]
+pub struct ChunkReceiver(Arc<Mutex<mpsc::Receiver<Chunk>>>);
+
+impl ChunkReceiver {
+    /// Block until the next chunk is available and return it.
+    ///
+    /// Once the input is exhausted, or reading it failed, this returns `None`.
+    pub fn recv(&self) -> Option<Chunk> {
+        // A poisoned lock only means another receiver panicked, which
+        // doesn't affect the channel itself.
+        let rx = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        rx.recv().ok()
+    }
+}
+
+impl Iterator for ChunkReceiver {
+    type Item = Chunk;
+
+    fn next(&mut self) -> Option<Chunk> {
+        self.recv()
+    }
+}
+
+/// A chunk of whole lines read by a pipeline.
+///
+/// Every chunk ends with a line terminator, except possibly the last one. A
+/// chunk dereferences to its bytes.
+#[derive(Debug)]
+pub struct Chunk {
+    seq: u64,
+    absolute_byte_offset: u64,
+    line_term: LineTerminator,
+    data: Vec<u8>,
+    pool: Pool,
+}
+
+impl Chunk {
+    /// The position of this chunk in the input, starting at `0`.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// The absolute byte offset of the start of this chunk in the input.
+    pub fn absolute_byte_offset(&self) -> u64 {
+        self.absolute_byte_offset
+    }
+
+    /// Return the contents of this chunk.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Return an iterator over the lines in this chunk, using the line
+    /// terminator of the pipeline's line buffer.
+    pub fn lines(&self) -> LineIter<'_> {
+        LineIter::new(self.line_term, &self.data)
+    }
+}
+
+impl ops::Deref for Chunk {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl Drop for Chunk {
+    fn drop(&mut self) {
+        self.pool.put(mem::take(&mut self.data));
+    }
+}
+
+/// A pool of buffers shared by the reader thread and all chunks.
+#[derive(Clone, Debug)]
+struct Pool {
+    buffers: Arc<Mutex<Vec<Vec<u8>>>>,
+    /// The maximum number of buffers to keep around.
+    max_len: usize,
+}
+
+impl Pool {
+    /// Take an empty buffer from the pool, or allocate a new one.
+    fn take(&self) -> Vec<u8> {
+        let mut buffers = self.buffers.lock().unwrap_or_else(|err| err.into_inner());
+        buffers.pop().unwrap_or_default()
+    }
+
+    /// Return a buffer to the pool.
+    fn put(&self, mut buf: Vec<u8>) {
+        let mut buffers = self.buffers.lock().unwrap_or_else(|err| err.into_inner());
+        if buffers.len() < self.max_len {
+            buf.clear();
+            buffers.push(buf);
+        }
+    }
+}
+
+/// Read chunks from `rdr` and send them to `tx` until EOF, or until every
+/// receiver has been dropped.
+fn read_chunks<R: io::Read>(
+    rdr: R,
+    mut line_buffer: LineBuffer,
+    tx: mpsc::SyncSender<Chunk>,
+    pool: Pool,
+) -> io::Result<()> {
+    let line_term = LineTerminator::byte(line_buffer.line_terminator());
+    let mut rdr = LineBufferReader::new(rdr, &mut line_buffer);
+    let mut seq = 0;
+    while rdr.fill()? {
+        let mut data = pool.take();
+        data.extend_from_slice(rdr.buffer());
+        let chunk = Chunk {
+            seq,
+            absolute_byte_offset: rdr.absolute_byte_offset(),
+            line_term,
+            data,
+            pool: pool.clone(),
+        };
+        rdr.consume_all();
+        if tx.send(chunk).is_err() {
+            // Nobody is listening anymore, so there's no point in reading on.
+            break;
+        }
+        seq += 1;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_buffer::{BufferAllocation, LineBufferBuilder};
+
+    const LINES: &str = "homer\nlisa\nmaggie\nbart\n";
+
+    #[test]
+    fn pipeline_in_order() {
+        let text = LINES.repeat(250);
+        let line_buffer = LineBufferBuilder::new().capacity(64).build();
+        let pipeline = PipelineBuilder::new()
+            .queue_len(2)
+            .build(io::Cursor::new(text.clone()), line_buffer);
+
+        let mut got = vec![];
+        let mut expected_seq = 0;
+        while let Some(chunk) = pipeline.recv() {
+            assert_eq!(chunk.seq(), expected_seq);
+            assert_eq!(chunk.absolute_byte_offset(), got.len() as u64);
+            assert!(chunk.ends_with(b"\n"));
+            got.extend_from_slice(&chunk);
+            expected_seq += 1;
+        }
+        assert!(expected_seq > 1);
+        assert_eq!(got, text.as_bytes());
+        pipeline.finish().unwrap();
+    }
+
+    #[test]
+    fn pipeline_workers() {
+        let text = LINES.repeat(1250);
+        let line_buffer = LineBufferBuilder::new().capacity(128).build();
+        let pipeline = Pipeline::new(io::Cursor::new(text.clone()), line_buffer);
+
+        let mut chunks: Vec<(u64, Vec<u8>, usize)> = thread::scope(|s| {
+            let workers: Vec<_> = (0..4)
+                .map(|_| {
+                    let chunks = pipeline.receiver();
+                    s.spawn(move || {
+                        chunks
+                            .map(|chunk| (chunk.seq(), chunk.to_vec(), chunk.lines().count()))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|w| w.join().unwrap())
+                .collect()
+        });
+        pipeline.finish().unwrap();
+
+        chunks.sort_by_key(|&(seq, _, _)| seq);
+        let total: usize = chunks.iter().map(|&(_, _, n)| n).sum();
+        assert_eq!(total, 5000);
+        let got: Vec<u8> = chunks.into_iter().flat_map(|(_, data, _)| data).collect();
+        assert_eq!(got, text.as_bytes());
+    }
+
+    #[test]
+    fn pipeline_error() {
+        let line_buffer = LineBufferBuilder::new()
+            .capacity(4)
+            .buffer_alloc(BufferAllocation::Error(0))
+            .build();
+        let pipeline = Pipeline::new(io::Cursor::new("homer\n"), line_buffer);
+        assert!(pipeline.recv().is_none());
+        assert!(pipeline.finish().is_err());
+    }
+
+    #[test]
+    fn pipeline_stops_early() {
+        let text = LINES.repeat(2500);
+        let line_buffer = LineBufferBuilder::new().capacity(16).build();
+        let pipeline = PipelineBuilder::new()
+            .queue_len(1)
+            .build(io::Cursor::new(text), line_buffer);
+        assert_eq!(pipeline.recv().unwrap().seq(), 0);
+        pipeline.finish().unwrap();
+    }
+}
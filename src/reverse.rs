@@ -0,0 +1,241 @@
+/*!
+Read the lines of a seekable input from last to first, like `tac`.
+
+A `ReverseLineReader` reads its input backwards from the end in blocks, so
+finding the most recent entries of a large log only reads as much of it as
+needed. Lines are the same as those yielded by `LineIter`, just in reverse
+order: line terminators are considered part of the line they terminate, and a
+final line without a line terminator is still a line.
+
+```no_run
+use ripline::reverse::ReverseLineReader;
+use std::fs::File;
+
+# fn example() -> std::io::Result<()> {
+let mut rdr = ReverseLineReader::new(File::open("app.log")?)?;
+while let Some(line) = rdr.next_line()? {
+    if line.starts_with(b"ERROR") {
+        println!("last error at byte offset {}", rdr.absolute_byte_offset());
+        break;
+    }
+}
+# Ok(())
+# }
+```
+*/
+
+use std::cmp;
+use std::io::{self, SeekFrom};
+
+use crate::line_buffer::DEFAULT_BUFFER_CAPACITY;
+use crate::lines::preceding;
+use crate::LineTerminator;
+
+/// A builder for constructing reverse line readers.
+#[derive(Clone, Debug)]
+pub struct ReverseLineReaderBuilder {
+    capacity: usize,
+    line_term: LineTerminator,
+}
+
+impl Default for ReverseLineReaderBuilder {
+    fn default() -> ReverseLineReaderBuilder {
+        ReverseLineReaderBuilder::new()
+    }
+}
+
+impl ReverseLineReaderBuilder {
+    /// Create a new builder for a reverse line reader.
+    pub fn new() -> ReverseLineReaderBuilder {
+        ReverseLineReaderBuilder {
+            capacity: DEFAULT_BUFFER_CAPACITY,
+            line_term: LineTerminator::default(),
+        }
+    }
+
+    /// Create a new reverse line reader over `rdr` from this builder's
+    /// configuration.
+    ///
+    /// This seeks `rdr` to its end to find out where to start reading.
+    pub fn build<R: io::Read + io::Seek>(&self, mut rdr: R) -> io::Result<ReverseLineReader<R>> {
+        let len = rdr.seek(SeekFrom::End(0))?;
+        Ok(ReverseLineReader {
+            rdr,
+            line_term: self.line_term,
+            capacity: cmp::max(1, self.capacity),
+            buf: vec![],
+            offset: len,
+            end: 0,
+            line_start: len,
+        })
+    }
+
+    /// Set the size of the blocks that are read at a time.
+    ///
+    /// Lines longer than this are read in more, increasingly large blocks.
+    /// This is set to the same default as the capacity of a line buffer. A
+    /// value of `0` is treated as `1`.
+    pub fn capacity(&mut self, capacity: usize) -> &mut ReverseLineReaderBuilder {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Set the line terminator used to find lines.
+    ///
+    /// By default, this is set to `b'\n'`.
+    pub fn line_terminator(&mut self, line_term: LineTerminator) -> &mut ReverseLineReaderBuilder {
+        self.line_term = line_term;
+        self
+    }
+}
+
+/// A reader that yields the lines of a seekable input from last to first.
+#[derive(Debug)]
+pub struct ReverseLineReader<R> {
+    rdr: R,
+    line_term: LineTerminator,
+    capacity: usize,
+    /// The input from `offset` up to the start of the line returned last,
+    /// followed by that line.
+    buf: Vec<u8>,
+    /// The absolute byte offset of the start of `buf`.
+    offset: u64,
+    /// The end of the lines in `buf` that haven't been returned yet.
+    end: usize,
+    /// The absolute byte offset of the line returned last.
+    line_start: u64,
+}
+
+impl<R: io::Read + io::Seek> ReverseLineReader<R> {
+    /// Create a new reverse line reader over `rdr` with a default
+    /// configuration.
+    pub fn new(rdr: R) -> io::Result<ReverseLineReader<R>> {
+        ReverseLineReaderBuilder::new().build(rdr)
+    }
+
+    /// Return a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.rdr
+    }
+
+    /// Unwrap this reverse line reader, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.rdr
+    }
+
+    /// Return the absolute byte offset of the line returned last by
+    /// `next_line`.
+    ///
+    /// Before the first line is returned, this is the length of the input.
+    /// Once all lines have been returned, it is `0`.
+    pub fn absolute_byte_offset(&self) -> u64 {
+        self.line_start
+    }
+
+    /// Return the line before the one returned last, or the last line of the
+    /// input on the first call. Once the first line of the input has been
+    /// returned, this returns `None`.
+    pub fn next_line(&mut self) -> io::Result<Option<&[u8]>> {
+        loop {
+            if self.end == 0 && self.offset == 0 {
+                return Ok(None);
+            }
+            let start = preceding(&self.buf[..self.end], self.line_term, 0);
+            // A line at the start of the buffer may have begun before it,
+            // unless the buffer starts at the start of the input.
+            if self.end > 0 && (start > 0 || self.offset == 0) {
+                let end = self.end;
+                self.end = start;
+                self.line_start = self.offset + start as u64;
+                return Ok(Some(&self.buf[start..end]));
+            }
+            self.read_block()?;
+        }
+    }
+
+    /// Read the block of input right before `offset` to the front of the
+    /// buffer, dropping the lines that have already been returned.
+    fn read_block(&mut self) -> io::Result<()> {
+        // Read larger blocks for long lines, so they aren't shifted through
+        // the buffer over and over again.
+        let len = cmp::min(cmp::max(self.capacity, self.end) as u64, self.offset) as usize;
+        self.offset -= len as u64;
+        self.buf.truncate(self.end);
+        self.buf.resize(self.end + len, 0);
+        self.buf.copy_within(..self.end, len);
+        self.end += len;
+        self.rdr.seek(SeekFrom::Start(self.offset))?;
+        self.rdr.read_exact(&mut self.buf[..len])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lines::LineIter;
+    use std::io::Cursor;
+
+    fn check(text: &str, line_term: LineTerminator) {
+        let expected: Vec<(u64, &[u8])> = {
+            let mut offset = 0;
+            let mut lines: Vec<(u64, &[u8])> = LineIter::new(line_term, text.as_bytes())
+                .map(|line| {
+                    offset += line.len() as u64;
+                    (offset - line.len() as u64, line)
+                })
+                .collect();
+            lines.reverse();
+            lines
+        };
+        for &capacity in [1, 2, 3, 7, 64, 10_000].iter() {
+            let mut rdr = ReverseLineReaderBuilder::new()
+                .capacity(capacity)
+                .line_terminator(line_term)
+                .build(Cursor::new(text))
+                .unwrap();
+            assert_eq!(rdr.absolute_byte_offset(), text.len() as u64);
+            let mut got = vec![];
+            while let Some(line) = rdr.next_line().unwrap() {
+                let line = line.to_vec();
+                got.push((rdr.absolute_byte_offset(), line));
+            }
+            assert!(rdr.next_line().unwrap().is_none());
+            let got: Vec<(u64, &[u8])> = got.iter().map(|(o, l)| (*o, &l[..])).collect();
+            assert_eq!(got, expected, "capacity {}", capacity);
+        }
+    }
+
+    #[test]
+    fn reverse_basics() {
+        let lf = LineTerminator::default();
+        check("", lf);
+        check("\n", lf);
+        check("\n\n\n", lf);
+        check("homer\nlisa\nmaggie\n", lf);
+        check("homer\nlisa\nmaggie", lf);
+        check("a", lf);
+    }
+
+    #[test]
+    fn reverse_long_lines() {
+        let text: String = (0..200)
+            .map(|i| format!("{}\n", "x".repeat((i * 37) % 101)))
+            .collect();
+        check(&text, LineTerminator::default());
+        check(
+            &format!("{}{}", text, "y".repeat(300)),
+            LineTerminator::default(),
+        );
+    }
+
+    #[test]
+    fn reverse_crlf() {
+        check("homer\r\nlisa\nmaggie\r\n\r\n", LineTerminator::crlf());
+        check("homer\r\nlisa\r", LineTerminator::crlf());
+    }
+
+    #[test]
+    fn reverse_terminator() {
+        check("homer\0lisa\0\0maggie", LineTerminator::byte(b'\0'));
+    }
+}
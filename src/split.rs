@@ -0,0 +1,234 @@
+/*!
+Read the lines of a byte range of a seekable input.
+
+This is useful for sharding work by byte ranges, in the same way as Hadoop
+input splits. A split `[start, end)` contains exactly the lines whose first
+byte falls in the range. The partial line at `start` belongs to the previous
+split and is skipped, and the last line is read past `end` until its line
+terminator. So a set of splits that cover an input without overlap together
+yield every line exactly once.
+
+```no_run
+use ripline::line_buffer::{LineBufferBuilder, LineBufferReader};
+use std::fs::File;
+
+# fn example() -> std::io::Result<()> {
+let mut line_buffer = LineBufferBuilder::new().build();
+let file = File::open("big.tsv")?;
+let mut lb_reader = LineBufferReader::with_range(file, &mut line_buffer, 1000..2000)?;
+while lb_reader.fill()? {
+    // Absolute offsets are offsets into the file.
+    let _offset = lb_reader.absolute_byte_offset();
+    lb_reader.consume_all();
+}
+# Ok(())
+# }
+```
+*/
+
+use std::io::{self, SeekFrom};
+use std::ops::Range;
+
+use bstr::ByteSlice;
+
+use crate::line_buffer::{LineBuffer, LineBufferReader};
+
+/// The size of the reads used to find the start of a line.
+const SEEK_BUFFER_SIZE: usize = 8 * (1 << 10); // 8 KB
+
+/// Seek `rdr` to the start of the first line that starts at or after
+/// `offset`, and return the position of that line.
+///
+/// A line starts at offset `0` or right after a line terminator. If there is
+/// no line start at or after `offset`, then `rdr` is seeked to EOF, or to
+/// `offset` if that is past EOF, and that position is returned.
+pub fn seek_line_start<R: io::Read + io::Seek>(
+    rdr: &mut R,
+    offset: u64,
+    line_term: u8,
+) -> io::Result<u64> {
+    if offset == 0 {
+        return rdr.seek(SeekFrom::Start(0));
+    }
+    // The line terminator right before `offset` may end the previous line,
+    // in which case a line starts right at `offset`.
+    let mut pos = rdr.seek(SeekFrom::Start(offset - 1))?;
+    let mut buf = vec![0; SEEK_BUFFER_SIZE];
+    loop {
+        let n = match rdr.read(&mut buf) {
+            Ok(n) => n,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        if n == 0 {
+            return rdr.seek(SeekFrom::Start(pos.max(offset)));
+        }
+        if let Some(i) = buf[..n].find_byte(line_term) {
+            return rdr.seek(SeekFrom::Start(pos + i as u64 + 1));
+        }
+        pos += n as u64;
+    }
+}
+
+/// A reader that stops at the end of the last line that starts before a given
+/// offset.
+///
+/// This is the reader used by `LineBufferReader::with_range`.
+#[derive(Debug)]
+pub struct SplitReader<R> {
+    rdr: R,
+    /// The absolute offset of the next byte read from `rdr`.
+    pos: u64,
+    /// The end of the split. Reading stops at the first line terminator at or
+    /// after `end - 1`.
+    end: u64,
+    line_term: u8,
+    done: bool,
+}
+
+impl<R: io::Read> SplitReader<R> {
+    /// Create a new split reader over `rdr`, which is positioned at the
+    /// absolute offset `pos`. Reading stops once the line that contains
+    /// `end - 1` has been read.
+    ///
+    /// If `pos >= end`, then nothing is read.
+    pub fn new(rdr: R, pos: u64, end: u64, line_term: u8) -> SplitReader<R> {
+        SplitReader {
+            rdr,
+            pos,
+            end,
+            line_term,
+            done: pos >= end,
+        }
+    }
+
+    /// Return a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.rdr
+    }
+
+    /// Return a mutable reference to the underlying reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.rdr
+    }
+
+    /// Unwrap this split reader, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.rdr
+    }
+}
+
+impl<R: io::Read> io::Read for SplitReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.done || buf.is_empty() {
+            return Ok(0);
+        }
+        let mut n = self.rdr.read(buf)?;
+        // The last line of the split ends at the first line terminator at or
+        // after `end - 1`.
+        let last = self.end - 1;
+        if self.pos + n as u64 > last {
+            let from = last.saturating_sub(self.pos) as usize;
+            if let Some(i) = buf[from..n].find_byte(self.line_term) {
+                n = from + i + 1;
+                self.done = true;
+            }
+        }
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<'b, R: io::Read + io::Seek> LineBufferReader<'b, SplitReader<R>> {
+    /// Create a new buffered reader over the lines of `rdr` whose first byte
+    /// falls in `range`.
+    ///
+    /// The partial line at the start of the range is skipped, and the last
+    /// line is read past the end of the range until its line terminator or
+    /// EOF. Absolute byte offsets reported by the returned reader are offsets
+    /// into `rdr`.
+    pub fn with_range(
+        mut rdr: R,
+        line_buffer: &'b mut LineBuffer,
+        range: Range<u64>,
+    ) -> io::Result<LineBufferReader<'b, SplitReader<R>>> {
+        let line_term = line_buffer.line_terminator();
+        let start = seek_line_start(&mut rdr, range.start, line_term)?;
+        let split = SplitReader::new(rdr, start, range.end, line_term);
+        let mut lb_reader = LineBufferReader::new(split, line_buffer);
+        lb_reader.reset(start);
+        Ok(lb_reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_buffer::LineBufferBuilder;
+    use std::io::Cursor;
+
+    /// Read the split `range` of `text` and return its contents along with the
+    /// absolute offset at which they started.
+    fn read_split(text: &str, range: Range<u64>, capacity: usize) -> (u64, String) {
+        let mut linebuf = LineBufferBuilder::new().capacity(capacity).build();
+        let mut rdr = LineBufferReader::with_range(Cursor::new(text), &mut linebuf, range).unwrap();
+        let start = rdr.absolute_byte_offset();
+        let mut got = String::new();
+        while rdr.fill().unwrap() {
+            assert_eq!(rdr.absolute_byte_offset(), start + got.len() as u64);
+            got.push_str(rdr.buffer().to_str().unwrap());
+            rdr.consume_all();
+        }
+        (start, got)
+    }
+
+    #[test]
+    fn split_basics() {
+        let text = "homer\nlisa\nmaggie\n";
+        assert_eq!(read_split(text, 0..1, 4), (0, "homer\n".to_string()));
+        assert_eq!(read_split(text, 0..6, 4), (0, "homer\n".to_string()));
+        assert_eq!(read_split(text, 0..7, 4), (0, "homer\nlisa\n".to_string()));
+        assert_eq!(read_split(text, 1..6, 4), (6, "".to_string()));
+        assert_eq!(read_split(text, 6..7, 4), (6, "lisa\n".to_string()));
+        assert_eq!(read_split(text, 5..11, 4), (6, "lisa\n".to_string()));
+        assert_eq!(read_split(text, 7..100, 4), (11, "maggie\n".to_string()));
+        assert_eq!(read_split(text, 18..100, 4), (18, "".to_string()));
+        assert_eq!(read_split(text, 3..3, 4), (6, "".to_string()));
+    }
+
+    #[test]
+    fn split_no_trailing_terminator() {
+        let text = "homer\nlisa";
+        assert_eq!(read_split(text, 2..8, 4), (6, "lisa".to_string()));
+        assert_eq!(read_split(text, 7..100, 4), (10, "".to_string()));
+    }
+
+    #[test]
+    fn splits_cover_every_line() {
+        let text: String = (0..500)
+            .map(|i| format!("{}\n", "x".repeat(i % 23)))
+            .collect();
+        let len = text.len() as u64;
+        for &size in [1, 3, 10, 64, 1000, 10_000].iter() {
+            let mut got = String::new();
+            let mut start = 0;
+            while start < len {
+                let end = (start + size).min(len);
+                got.push_str(&read_split(&text, start..end, 16).1);
+                start = end;
+            }
+            assert_eq!(got, text, "split size {}", size);
+        }
+    }
+
+    #[test]
+    fn seek_to_line_start() {
+        let mut rdr = Cursor::new("homer\nlisa\n");
+        assert_eq!(seek_line_start(&mut rdr, 0, b'\n').unwrap(), 0);
+        assert_eq!(seek_line_start(&mut rdr, 3, b'\n').unwrap(), 6);
+        assert_eq!(seek_line_start(&mut rdr, 6, b'\n').unwrap(), 6);
+        assert_eq!(seek_line_start(&mut rdr, 7, b'\n').unwrap(), 11);
+        assert_eq!(rdr.position(), 11);
+        assert_eq!(seek_line_start(&mut rdr, 50, b'\n').unwrap(), 50);
+    }
+}